@@ -0,0 +1,109 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[path = "../src/core/mixer.rs"]
+mod mixer;
+
+const TAPE_COUNT: usize = 8;
+/// Frames in a block, both sides of the bus are this long
+const BLOCK_LEN: usize = 512;
+
+/// The mixing loop before it was split into blocks, kept here as the baseline
+/// Solo is resolved for every sample like it was then, the pan gains are worked out
+/// once up front so only the loop itself is compared
+fn mix_per_sample(
+    output: &mut [Vec<f32>; 2],
+    tapes: &[Vec<f32>],
+    volumes: &[f32],
+    solos: &[bool],
+    pans: &[f32],
+    peaks: &mut [f32],
+) {
+    let pan_gains: [[f32; 2]; TAPE_COUNT] = std::array::from_fn(|i| mixer::pan_gains(pans[i]));
+    let [left, right] = output;
+    for (index, (left, right)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+        let mut sample = [0.0; 2];
+        let is_there_any_solo = solos.iter().any(|solo| *solo);
+        for (i, tape) in tapes.iter().enumerate() {
+            let tape_sample = if !is_there_any_solo || solos[i] {
+                tape[index] * volumes[i]
+            } else {
+                0.0
+            };
+            peaks[i] = f32::max(peaks[i], tape_sample.abs());
+            let [left_gain, right_gain] = pan_gains[i];
+            sample[0] += tape_sample * left_gain;
+            sample[1] += tape_sample * right_gain;
+        }
+        *left = sample[0];
+        *right = sample[1];
+    }
+}
+
+/// Gains are resolved once per block, then every side is mixed a block at a time
+fn mix_blocks(
+    output: &mut [Vec<f32>; 2],
+    tapes: &[Vec<f32>],
+    volumes: &[f32; TAPE_COUNT],
+    solos: &[bool; TAPE_COUNT],
+    pans: &[f32; TAPE_COUNT],
+    peaks: &mut [f32],
+) {
+    let gains = mixer::mix_gains(volumes, solos, pans);
+    let tapes: [&[f32]; TAPE_COUNT] = std::array::from_fn(|i| &tapes[i][..]);
+    for (output, gains) in output.iter_mut().zip(&gains) {
+        mixer::mix_tapes(output, &tapes, gains, peaks);
+    }
+}
+
+fn bench_mixer(c: &mut Criterion) {
+    let tapes: Vec<Vec<f32>> = (0..TAPE_COUNT)
+        .map(|i| {
+            (0..BLOCK_LEN)
+                .map(|j| ((i * BLOCK_LEN + j) as f32 * 0.01).sin())
+                .collect()
+        })
+        .collect();
+    let volumes = [0.8; TAPE_COUNT];
+    let pans: [f32; TAPE_COUNT] = std::array::from_fn(|i| i as f32 / 3.5 - 1.0);
+    let mut output = [vec![0.0; BLOCK_LEN], vec![0.0; BLOCK_LEN]];
+    let mut peaks = [0.0; TAPE_COUNT];
+
+    // Both mix 8 panned tapes into a stereo bus
+    let mut group = c.benchmark_group("mix 8 tapes");
+    for (name, solos) in [
+        ("no solo", [false; TAPE_COUNT]),
+        (
+            "one solo",
+            [true, false, false, false, false, false, false, false],
+        ),
+    ] {
+        group.bench_function(format!("per sample, {}", name), |b| {
+            b.iter(|| {
+                mix_per_sample(
+                    black_box(&mut output),
+                    black_box(&tapes),
+                    &volumes,
+                    &solos,
+                    &pans,
+                    &mut peaks,
+                )
+            })
+        });
+        group.bench_function(format!("block, {}", name), |b| {
+            b.iter(|| {
+                mix_blocks(
+                    black_box(&mut output),
+                    black_box(&tapes),
+                    &volumes,
+                    &solos,
+                    &pans,
+                    &mut peaks,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_mixer);
+criterion_main!(benches);
@@ -1,6 +1,11 @@
 use crate::core::*;
-use crate::features::{Metronome, Tape};
+use crate::features::{
+    Arrangement, AutomationLane, AutomationMode, CaptureBuffer, CircularBuffer, InputStrip,
+    Metronome, Punch, Synth, Tape, TapeMix, AUTOMATION_RELEASE_TIME, MODULATION_INTERVAL,
+};
+use crate::Config;
 use ringbuf::{traits::*, HeapCons, HeapProd};
+use std::collections::VecDeque;
 
 pub struct TapeModel {
     pub tapes: [Tape<f32>; TAPE_COUNT],
@@ -15,343 +20,1697 @@ impl TapeModel {
     }
 }
 
-/// Used to transfer data to the audio thread
-/// In this context audio thread is the thread that communicates
-/// with input and output streams(each has its own thread)
+/// Lives inside the output stream callback, every output block is mixed here
+/// Input frames are pulled from the input stream's ring buffer in lock-step
+/// with the output, so playback keeps going even if the input stalls
+/// Nothing in here allocates, tape buffers are prepared by the Worker and swapped in
 pub struct AudioModel {
     pub tape_length: usize,
-    pub recording_tape: Vec<Input>,
+    pub bar_count: usize,
+    /// Empty recording tapes the Worker has prepared, preallocated with MAX_RECORDINGS
+    pub recording_tapes: Vec<TapeAudio>,
+    /// The primary tape and the tapes recorded with it, the primary tape comes first
+    pub recordings: Vec<Recording>,
     pub tape_model: TapeModel,
-    pub input_consumer: HeapCons<Input>,
-    pub action_consumer: HeapCons<ModulAction>,
+    pub input_consumer: HeapCons<f32>,
+    pub action_consumer: HeapCons<ScheduledAction>,
+    /// Sorted by frame, preallocated with MAX_SCHEDULED_ACTIONS
+    pub scheduled_actions: Vec<PendingAction>,
+    /// Frames since the output stream started, scheduled actions are timed against this
+    pub frame_position: u64,
     pub audio_message_producer: HeapProd<ModulMessage>,
-    pub audio_message_consumer: HeapCons<ModulMessage>,
+    pub job_producer: HeapProd<Job>,
+    /// Jobs that didn't fit in the job queue, preallocated with MAX_PENDING_JOBS
+    /// They are sent first in the next output buffer, nothing is freed on the audio thread
+    pub pending_jobs: VecDeque<Job>,
+    pub done_consumer: HeapCons<Done>,
     pub is_recording: bool,
+    pub is_armed: bool,
+    pub record_settings: RecordSettings,
+    /// Input that came in while armed, it is put in front of the recording when it starts
+    /// One for every input channel
+    pub pre_roll: Vec<CircularBuffer>,
+    /// Frames left until a recording that lasts one loop stops
+    pub recording_remaining: Option<usize>,
+    /// With auto-advance the next empty tape is recorded on the next loop boundary
+    pub is_advance_pending: bool,
+    /// Kept up to date by the Worker, a tape is empty if it is silent
+    pub empty_tapes: [bool; TAPE_COUNT],
+    /// Input is always written here, independent of the recording tape
+    pub capture_buffer: CaptureBuffer,
+    /// Until the first recording defines the loop, it is recorded from the start of the recording tape
+    pub is_free_running: bool,
+    pub free_recording_length: usize,
     pub is_recording_playback: bool,
     pub is_play_through: bool,
-    pub audio_index: usize,
+    /// Tape, capture and metronome positions are all read from here
+    pub timeline: Timeline,
+    /// Tapes, recording and the metronome only move while playing
+    pub transport: TransportState,
     pub primary_tape: usize,
     pub secondary_tapes: [bool; TAPE_COUNT],
-    pub output_producer: HeapProd<f32>,
+    /// Tapes that are recorded together with the primary tape
+    pub record_enabled: [bool; TAPE_COUNT],
+    /// Input channels every tape records from
+    pub input_routes: [InputRoute; TAPE_COUNT],
+    pub punch_regions: [Option<PunchRegion>; TAPE_COUNT],
     pub writing_tape: Vec<f32>,
     pub sample_averages: [f32; TAPE_COUNT + 1],
-    pub samples_for_graphs: [[f32; SAMPLE_GRAPH_SIZE]; TAPE_COUNT],
     pub show_beat: bool,
-    pub beat_index: u32,
+    pub beat_index: u64,
     pub metronome: Metronome,
+    pub input_strip: InputStrip,
+    pub input_peak: f32,
+    pub sample_rate: u32,
+    pub input_channel_count: usize,
     pub output_channel_count: usize,
-    pub log_producer: HeapProd<String>,
+    /// Interleaved, as it comes from the input device
+    pub input_block: Vec<f32>,
+    /// The input block split into its channels, then the two channels of the synth
+    pub input_channels: Vec<Vec<f32>>,
+    /// The input of the tapes that are recorded, it is played through and written
+    pub input_bus: TapeAudio,
+    /// Every tape is panned into this, it is sent to the main output pair
+    pub master_bus: TapeAudio,
+    /// What the performer hears, sent to the cue output pair if there is one
+    pub cue_bus: TapeAudio,
+    /// The metronome, it is only in the cue mix if there is one
+    pub click_bus: Vec<f32>,
+    pub output_settings: OutputSettings,
+    pub master_peaks: [f32; TAPE_CHANNELS],
+    /// Volume and pan of every tape, for each side of the master bus
+    pub gains: [[f32; TAPE_COUNT]; TAPE_CHANNELS],
+    pub scenes: [Option<[TapeMix; TAPE_COUNT]>; SCENE_COUNT],
+    pub scene_fade: Option<SceneFade>,
+    /// Volume of every tape over one loop
+    pub automation_lanes: [AutomationLane; TAPE_COUNT],
+    pub automation_modes: [AutomationMode; TAPE_COUNT],
+    /// Lanes that were written or cleared since their graph was sent
+    pub is_automation_changed: [bool; TAPE_COUNT],
+    /// Modulation and duckers of the tapes and the master bus, the song is rendered through a copy
+    pub tape_mixer: TapeMixer,
+    /// Played into the input channels after the channels of the device
+    pub synth: Synth,
+    /// Frames until a touched lane is let go
+    pub touch_remaining: [usize; TAPE_COUNT],
+    /// Latched lanes are written until the transport stops
+    pub is_latched: [bool; TAPE_COUNT],
+    /// Swapped with the arrangements that come from the UI, the old one is freed by the Worker
+    pub arrangement: Box<Arrangement>,
+    /// Handed to the Worker to render the arrangement, it comes back when the file is written
+    pub song: Option<Box<Song>>,
+    /// The arrangement decides which tapes play and how, the transport stops at the end of the song
+    pub is_song_mode: bool,
+    /// The section that is playing in song mode
+    pub section: Option<usize>,
+    pub latency: Latency,
 }
 
-pub struct Input {
-    pub index: usize,
-    pub sample: f32,
+/// Sample rates and channel counts of the devices
+pub struct StreamFormat {
+    pub input_sample_rate: u32,
+    pub output_sample_rate: u32,
+    pub input_channel_count: usize,
+    pub output_channel_count: usize,
+}
+
+/// Ring buffers between the output callback and the other threads
+pub struct AudioQueues {
+    pub input_consumer: HeapCons<f32>,
+    pub action_consumer: HeapCons<ScheduledAction>,
+    pub audio_message_producer: HeapProd<ModulMessage>,
+    pub job_producer: HeapProd<Job>,
+    pub done_consumer: HeapCons<Done>,
+}
+
+/// A recording tape and the tape it is committed to
+pub struct Recording {
+    id: usize,
+    audio: TapeAudio,
+}
+
+/// The gains right before a scene was recalled, they fade to the gains of the scene
+pub struct SceneFade {
+    from: [[f32; TAPE_COUNT]; TAPE_CHANNELS],
+    length: usize,
+    position: usize,
+}
+
+/// An action with its timestamp resolved to a frame
+#[derive(Debug)]
+pub struct PendingAction {
+    frame: u64,
+    action: ModulAction,
 }
 
+/// Input frames allowed to wait in the ring buffer, in output blocks
+/// Anything above this is dropped so latency can't creep up
+const MAX_QUEUED_BLOCKS: usize = 2;
+
 impl AudioModel {
-    pub fn update(&mut self) {
-        let mut sample_averages = [0.0; TAPE_COUNT + 1];
-        let sample_count = self.input_consumer.occupied_len();
+    /// Everything is allocated here, before the output stream starts
+    pub fn new(
+        config: &Config,
+        timeline: Timeline,
+        tape_length: usize,
+        format: StreamFormat,
+        capture_buffer: CaptureBuffer,
+        queues: AudioQueues,
+    ) -> Self {
+        let input_channel_count = format.input_channel_count;
+        // The synth is played into two more channels after the channels of the device
+        let channel_count = input_channel_count + TAPE_CHANNELS;
+        let free_recording_capacity =
+            (MAX_FREE_LOOP_LENGTH * format.input_sample_rate as f32) as usize;
+        // The writing tape is interleaved stereo
+        let ten_minutes_in_seconds = 10 * 60;
+        let writing_tape_capacity =
+            format.input_sample_rate as usize * TAPE_CHANNELS * ten_minutes_in_seconds;
 
-        self.show_beat = self.metronome.show_beat();
-        let r = self
-            .audio_message_producer
-            .try_push(ModulMessage::ShowBeat(self.show_beat));
-        match r {
-            Ok(_) => {}
-            Err(_) => {
-                dbg!("UI is busy");
+        // Only one free running recording is needed, it defines the loop
+        let mut recording_tapes = Vec::with_capacity(MAX_RECORDINGS);
+        if config.is_free_running {
+            recording_tapes.push(std::array::from_fn(|_| vec![0.0; free_recording_capacity]));
+        } else {
+            for _ in 0..MAX_RECORDINGS {
+                recording_tapes.push(std::array::from_fn(|_| {
+                    vec![0.0; tape_length * MAX_LOOP_MULTIPLE]
+                }));
             }
         }
 
+        Self {
+            tape_length,
+            bar_count: config.bar_count,
+            recording_tapes,
+            recordings: Vec::with_capacity(MAX_RECORDINGS),
+            tape_model: TapeModel::new(tape_length),
+            input_consumer: queues.input_consumer,
+            action_consumer: queues.action_consumer,
+            scheduled_actions: Vec::with_capacity(MAX_SCHEDULED_ACTIONS),
+            frame_position: 0,
+            audio_message_producer: queues.audio_message_producer,
+            job_producer: queues.job_producer,
+            pending_jobs: VecDeque::with_capacity(MAX_PENDING_JOBS),
+            done_consumer: queues.done_consumer,
+            is_recording: false,
+            is_armed: false,
+            record_settings: RecordSettings::default(),
+            pre_roll: (0..channel_count)
+                .map(|_| {
+                    CircularBuffer::new((MAX_PRE_ROLL * format.input_sample_rate as f32) as usize)
+                })
+                .collect(),
+            recording_remaining: None,
+            is_advance_pending: false,
+            empty_tapes: [true; TAPE_COUNT],
+            capture_buffer,
+            is_free_running: config.is_free_running,
+            free_recording_length: 0,
+            is_recording_playback: false,
+            is_play_through: false,
+            timeline,
+            transport: TransportState::Playing,
+            primary_tape: 0,
+            secondary_tapes: [false; TAPE_COUNT],
+            record_enabled: [false; TAPE_COUNT],
+            input_routes: [InputRoute::default(); TAPE_COUNT],
+            punch_regions: [None; TAPE_COUNT],
+            writing_tape: Vec::with_capacity(writing_tape_capacity),
+            sample_averages: [0.0; TAPE_COUNT + 1],
+            show_beat: false,
+            beat_index: 0,
+            metronome: Metronome::default(),
+            input_strip: InputStrip::new(format.input_sample_rate, input_channel_count),
+            input_peak: 0.0,
+            sample_rate: format.output_sample_rate,
+            input_channel_count,
+            output_channel_count: format.output_channel_count,
+            input_block: vec![0.0; MAX_BLOCK_FRAMES * input_channel_count],
+            input_channels: vec![vec![0.0; MAX_BLOCK_FRAMES]; channel_count],
+            input_bus: std::array::from_fn(|_| vec![0.0; MAX_BLOCK_FRAMES]),
+            master_bus: std::array::from_fn(|_| vec![0.0; MAX_BLOCK_FRAMES]),
+            cue_bus: std::array::from_fn(|_| vec![0.0; MAX_BLOCK_FRAMES]),
+            click_bus: vec![0.0; MAX_BLOCK_FRAMES],
+            output_settings: OutputSettings::default(),
+            master_peaks: [0.0; TAPE_CHANNELS],
+            gains: [[0.0; TAPE_COUNT]; TAPE_CHANNELS],
+            scenes: [None; SCENE_COUNT],
+            scene_fade: None,
+            automation_lanes: [AutomationLane::default(); TAPE_COUNT],
+            automation_modes: [AutomationMode::Off; TAPE_COUNT],
+            is_automation_changed: [false; TAPE_COUNT],
+            touch_remaining: [0; TAPE_COUNT],
+            is_latched: [false; TAPE_COUNT],
+            tape_mixer: TapeMixer::new(format.output_sample_rate, Box::default()),
+            synth: Synth::new(format.output_sample_rate),
+            arrangement: Box::default(),
+            song: Some(Box::default()),
+            is_song_mode: false,
+            section: None,
+            latency: Latency::default(),
+        }
+    }
+
+    pub fn process(&mut self, output: &mut [f32]) {
+        self.receive_done();
+        self.send_pending_jobs();
+        self.check_user_input();
+        self.sample_averages = [0.0; TAPE_COUNT + 1];
+        self.input_peak = 0.0;
+        self.master_peaks = [0.0; TAPE_CHANNELS];
+        self.tape_mixer.gain_reductions = [0.0; TAPE_COUNT + 1];
+
+        let output_channel_count = self.output_channel_count;
+        let frame_count = output.len() / output_channel_count;
+        self.keep_latency_bounded(frame_count);
+
+        for block in output.chunks_mut(MAX_BLOCK_FRAMES * output_channel_count) {
+            self.process_block(block);
+        }
+
+        self.show_beat = self.metronome.show_beat();
+        self.send(ModulMessage::ShowBeat(self.show_beat));
         self.beat_index = self.metronome.get_beat_index();
-        let r = self
-            .audio_message_producer
-            .try_push(ModulMessage::BeatIndex(self.beat_index));
-        match r {
-            Ok(_) => {}
-            Err(_) => {
-                dbg!("UI is busy");
+        self.send(ModulMessage::BeatIndex(self.beat_index));
+        self.send(ModulMessage::Position(self.timeline.position()));
+        self.send(ModulMessage::Transport(self.transport));
+        self.send(ModulMessage::SampleAverages(self.sample_averages));
+        self.send(ModulMessage::Latency(self.latency));
+        self.send(ModulMessage::MasterLevel(self.master_peaks));
+        self.send(ModulMessage::GainReductions(
+            self.tape_mixer.gain_reductions,
+        ));
+        let tape_mixes = std::array::from_fn(|id| self.tape_model.tapes[id].get_mix());
+        self.send(ModulMessage::TapeMixes(tape_mixes));
+        self.send(ModulMessage::Section(self.section));
+        for id in 0..TAPE_COUNT {
+            if self.is_automation_changed[id] {
+                self.is_automation_changed[id] = false;
+                let points = self.automation_lanes[id].graph();
+                self.send(ModulMessage::AutomationGraph { id, points });
             }
         }
+        self.send(ModulMessage::InputLevel {
+            peak: self.input_peak,
+            is_gate_open: self.input_strip.is_gate_open(),
+        });
+    }
 
-        while !self.input_consumer.is_empty() {
-            let t = self.input_consumer.try_pop().unwrap();
-            let t_index = t.index; // this is the cursor(kind of)
-            self.audio_index = t_index;
-            let t_sample = t.sample; // this is the signal that came from the input channel
+    /// Drops the oldest input frames when the input runs ahead of the output,
+    /// then measures how much input is buffered
+    fn keep_latency_bounded(&mut self, frame_count: usize) {
+        let input_channel_count = self.input_channel_count;
+        let queued_frames = self.input_consumer.occupied_len() / input_channel_count;
+        let max_queued_frames = frame_count * (MAX_QUEUED_BLOCKS + 1);
+        if queued_frames > max_queued_frames {
+            // Keep the block that is mixed now and one more to absorb callback jitter
+            let excess_frames = queued_frames - frame_count * 2;
+            self.input_consumer
+                .skip(excess_frames * input_channel_count);
+            self.latency.overruns += 1;
+        }
 
-            if self.is_recording {
-                self.recording_tape.push(t);
-            }
+        let queued_frames = self.input_consumer.occupied_len() / input_channel_count;
+        // Input that is still waiting after this block plus the block that is being filled
+        self.latency.frames = queued_frames.max(frame_count);
+        self.latency.milliseconds = self.latency.frames as f32 * 1000.0 / self.sample_rate as f32;
+    }
 
-            // send audio to output
-            let mut sample: f32 = 0.0;
-            let is_there_any_solo = self.tape_model.tapes.iter().any(|tape| tape.is_solo());
-            for (tape, average) in self.tape_model.tapes.iter().zip(sample_averages.iter_mut()) {
-                let tape_sample = if !is_there_any_solo {
-                    tape.audio[t_index] * tape.get_volume()
-                } else {
-                    if tape.is_solo() {
-                        tape.audio[t_index] * tape.get_volume()
-                    } else {
-                        0.0
+    fn process_block(&mut self, block: &mut [f32]) {
+        let input_channel_count = self.input_channel_count;
+        let output_channel_count = self.output_channel_count;
+        let frame_count = block.len() / output_channel_count;
+
+        let input_len = frame_count * input_channel_count;
+        let read = self
+            .input_consumer
+            .pop_slice(&mut self.input_block[..input_len]);
+        if read < input_len {
+            self.input_block[read..input_len].fill(0.0);
+            self.latency.underruns += 1;
+        }
+        let input_peak = self
+            .input_strip
+            .process(&mut self.input_block[..input_len], input_channel_count);
+        self.input_peak = self.input_peak.max(input_peak);
+        self.split_input(frame_count);
+
+        // A block can run over the end of the loop or the end of a shorter tape,
+        // then it is mixed in segments that each play a contiguous part of every tape
+        let mut offset = 0;
+        while offset < frame_count {
+            self.apply_due_actions();
+            self.advance_recording();
+            // A segment ends where the next action is due
+            let mut len = frame_count - offset;
+            if let Some(pending) = self.scheduled_actions.first() {
+                len = len.min((pending.frame - self.frame_position) as usize);
+            }
+            let mut section = None;
+            if self.is_song_mode && self.transport == TransportState::Playing {
+                match self.follow_arrangement() {
+                    Some((index, section_length)) => {
+                        len = len.min(section_length);
+                        section = Some(index);
+                    }
+                    None => {
+                        self.set_transport(TransportState::Stopped);
+                        self.locate(0);
+                        self.log(LogMessage::SongEnded);
                     }
-                };
-                if tape_sample > *average {
-                    *average = tape_sample;
                 }
-                sample += tape_sample;
             }
-
-            let mut sum = sample;
-            if self.is_play_through {
-                sum += t_sample;
-                if t_sample > sample_averages[8] {
-                    sample_averages[8] = t_sample;
+            if self.transport == TransportState::Playing {
+                let start = self.audio_index(); // this is the cursor(kind of)
+                len = len.min(self.tape_length - start);
+                for id in 0..TAPE_COUNT {
+                    len = len.min(self.tape_model.tapes[id].length() - self.tape_index(id));
+                }
+                // Automation moves from point to point, a segment never runs over the next one
+                if self.is_automated() {
+                    len = len.min(AutomationLane::frames_to_next_point(
+                        start,
+                        self.tape_length,
+                    ));
                 }
+                // Modulation is updated between segments, they are kept short while there are routes
+                if self.tape_mixer.is_modulated() {
+                    len = len.min(MODULATION_INTERVAL);
+                }
+                self.modulate(offset, len);
+                let gains = self.segment_gains(section, start, len);
+                self.process_segment(offset, start, len, &gains);
+                self.timeline.advance(len);
+            } else {
+                self.process_stopped_segment(offset, len);
             }
+            offset += len;
+            self.frame_position += len as u64;
+        }
 
-            // sine wave for metronome
-            if self.metronome.is_running && self.metronome.show_beat() {
-                let first_beat = self.metronome.get_beat_index() % 4 == 0;
-                let freq: f32 = if first_beat {
-                    utils::C_FREQ
-                } else {
-                    utils::A_FREQ
-                };
-                let volume = 0.02;
-                sum +=
-                    (t_index as f32 * 2.0 * std::f32::consts::PI * freq / 44100.0).sin() * volume;
-
-                // println!("t_index: {}, t_sample: {}", t_index, t_sample);
-            }
-            // ========
-
-            self.metronome.update();
-
-            let r = self.output_producer.try_push(sum);
-            match r {
-                Ok(_) => {}
-                Err(_e) => {
-                    self.log_producer
-                        .try_push(format!(
-                            "buffer is full: {}",
-                            self.output_producer.occupied_len()
-                        ))
-                        .unwrap();
+        for (peak, channel) in self.master_peaks.iter_mut().zip(&self.master_bus) {
+            *peak = channel[..frame_count]
+                .iter()
+                .fold(*peak, |peak, sample| peak.max(sample.abs()));
+        }
+
+        self.route_output(block);
+    }
+
+    /// The section that is playing and the frames left in it, None once the song is over
+    fn follow_arrangement(&mut self) -> Option<(usize, usize)> {
+        let position = self.timeline.position();
+        let bar = self.timeline.musical(position).bar - 1;
+        self.section = None;
+        let (index, end) = self.arrangement.section_at(bar)?;
+        self.section = Some(index);
+        // Rounding can put the end of the section right on the position, a segment is never empty
+        let section_length = self.timeline.bar_position(end).saturating_sub(position);
+        Some((index, (section_length as usize).max(1)))
+    }
+
+    /// Gains of a segment that starts at `index` in the loop, with the automation, the scene fade
+    /// and the section of the arrangement that is playing
+    fn segment_gains(
+        &mut self,
+        section: Option<usize>,
+        index: usize,
+        len: usize,
+    ) -> [[f32; TAPE_COUNT]; TAPE_CHANNELS] {
+        let mixes = std::array::from_fn(|id| self.tape_model.tapes[id].get_mix());
+        let mixes = match section {
+            Some(section) => self.arrangement.mixes(section, mixes, &self.scenes),
+            None => mixes,
+        };
+        let mixes = self.automate(mixes, index, len);
+        let mixes = self.tape_mixer.apply(mixes);
+        let volumes = std::array::from_fn(|id| mixes[id].get_volume());
+        let solos = std::array::from_fn(|id| mixes[id].is_solo);
+        let pans = std::array::from_fn(|id| mixes[id].pan);
+        self.gains = mixer::mix_gains(&volumes, &solos, &pans);
+        // Gains move once per segment, that is smooth enough for a crossfade
+        if let Some(fade) = self.scene_fade.as_mut() {
+            let progress = (fade.position as f32 / fade.length as f32).min(1.0);
+            for (gains, from) in self.gains.iter_mut().zip(&fade.from) {
+                for (gain, from) in gains.iter_mut().zip(from) {
+                    *gain = from + (*gain - from) * progress;
                 }
             }
-            if self.is_recording_playback {
-                sample += t_sample;
+            fade.position += len;
+            if fade.position >= fade.length {
+                self.scene_fade = None;
             }
-            self.writing_tape.push(sample);
         }
+        match section {
+            Some(section) => self.arrangement.gains(section, &mixes),
+            None => self.gains,
+        }
+    }
+
+    /// Follows the levels of the segment that is about to play, the input is followed too
+    fn modulate(&mut self, offset: usize, len: usize) {
+        let indices = std::array::from_fn(|id| self.tape_index(id));
+        let input_peak = if self.tape_mixer.is_modulated() {
+            self.input_channels
+                .iter()
+                .flat_map(|channel| &channel[offset..offset + len])
+                .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+        } else {
+            0.0
+        };
+        let beats = self.timeline.beats(self.timeline.position());
+        self.tape_mixer
+            .modulate(&self.tape_model.tapes, indices, len, input_peak, beats);
+    }
 
-        let r = self
-            .audio_message_producer
-            .try_push(ModulMessage::AudioIndex(self.audio_index));
-        match r {
-            Ok(_) => {}
-            Err(_) => {
-                dbg!("UI is busy");
+    fn is_automated(&self) -> bool {
+        self.automation_modes
+            .iter()
+            .any(|mode| *mode != AutomationMode::Off)
+    }
+
+    /// Writes the volume of the tapes that are being written to their lanes,
+    /// the other lanes decide the volume of `mixes`
+    fn automate(
+        &mut self,
+        mut mixes: [TapeMix; TAPE_COUNT],
+        index: usize,
+        len: usize,
+    ) -> [TapeMix; TAPE_COUNT] {
+        for (id, mix) in mixes.iter_mut().enumerate() {
+            self.touch_remaining[id] = self.touch_remaining[id].saturating_sub(len);
+            let volume = self.tape_model.tapes[id].get_mix().get_volume();
+            let lane = &mut self.automation_lanes[id];
+            match self.automation_modes[id] {
+                AutomationMode::Off => {}
+                AutomationMode::Overwrite => {
+                    lane.write(index, self.tape_length, volume);
+                    self.is_automation_changed[id] = true;
+                }
+                AutomationMode::Touch if self.touch_remaining[id] > 0 => {
+                    lane.write(index, self.tape_length, volume);
+                    self.is_automation_changed[id] = true;
+                }
+                AutomationMode::Latch if self.is_latched[id] => {
+                    lane.write(index, self.tape_length, volume);
+                    self.is_automation_changed[id] = true;
+                }
+                _ => *mix = lane.read(*mix, index, self.tape_length),
             }
         }
 
-        // This is to prevent left/right switching
+        mixes
+    }
+
+    /// Changing the volume or mute of a tape starts writing its lane in touch and latch mode
+    /// The volume picks up where the lane is, so it doesn't jump
+    fn touch(&mut self, id: usize) {
+        let mode = self.automation_modes[id];
+        if mode != AutomationMode::Touch && mode != AutomationMode::Latch {
+            return;
+        }
+        let is_writing = self.touch_remaining[id] > 0 || self.is_latched[id];
+        let volume = self.automation_lanes[id].get(self.audio_index(), self.tape_length);
+        if let Some(volume) = volume.filter(|volume| !is_writing && *volume > 0.0) {
+            let tape = &mut self.tape_model.tapes[id];
+            tape.set_mix(TapeMix {
+                volume,
+                ..tape.get_mix()
+            });
+        }
+        self.touch_remaining[id] =
+            self.timeline
+                .position_of_seconds(AUTOMATION_RELEASE_TIME as f64) as usize;
+        self.is_latched[id] = mode == AutomationMode::Latch;
+    }
+
+    /// The main mix and the cue mix each go to their output pair, other channels are silent
+    /// Mono devices get both sides of the main mix
+    fn route_output(&self, block: &mut [f32]) {
         let output_channel_count = self.output_channel_count;
-        if self.output_producer.occupied_len() % output_channel_count != 0 {
-            self.log_producer
-                .try_push(format!(
-                    "output_producer.len % {} is not 0, fixing",
-                    output_channel_count
-                ))
-                .unwrap();
-            self.output_producer.try_push(0.0).unwrap();
-        }
-
-        if sample_count > 0 {
-            self.sample_averages = sample_averages;
-            let r = self
-                .audio_message_producer
-                .try_push(ModulMessage::SampleAverages(self.sample_averages));
-            match r {
-                Ok(_) => {}
-                Err(_) => {
-                    dbg!("UI is busy");
+        let [left, right] = &self.master_bus;
+        for (frame, output) in block.chunks_exact_mut(output_channel_count).enumerate() {
+            if output_channel_count == 1 {
+                output[0] = (left[frame] + right[frame]) * 0.5;
+                continue;
+            }
+            output.fill(0.0);
+            let pairs = [
+                Some((self.output_settings.main_pair, &self.master_bus)),
+                self.output_settings
+                    .cue_pair
+                    .map(|pair| (pair, &self.cue_bus)),
+            ];
+            for (pair, bus) in pairs.into_iter().flatten() {
+                for (side, channel) in bus.iter().enumerate() {
+                    if let Some(sample) = output.get_mut(pair * TAPE_CHANNELS + side) {
+                        *sample += channel[frame];
+                    }
                 }
             }
         }
-
-        self.check_user_input();
     }
 
-    fn update_waveform(&mut self, id: usize, audio: &Vec<f32>) {
-        let mut counter = 0;
-        let mut temp = vec![];
-        let mut sum = 0.0;
-        // Adding 1 to make sure that we have samples more than SAMPLE_GRAPH_SIZE
-        // Be my guest if you find a smarter way to do that
-        let size = audio.len() / (SAMPLE_GRAPH_SIZE + 1);
-        for sample in audio {
-            if *sample < 0.0 {
-                sum -= *sample
-            } else {
-                sum += *sample
-            };
-            if counter >= size {
-                temp.push(sum);
-                sum = 0.0;
-                counter = 0;
+    /// Deinterleaves the input block and plays the synth after it,
+    /// then routes the input of the tapes that are recorded to the input bus
+    fn split_input(&mut self, frame_count: usize) {
+        let input_channel_count = self.input_channel_count;
+        for (frame, input) in self.input_block[..frame_count * input_channel_count]
+            .chunks_exact(input_channel_count)
+            .enumerate()
+        {
+            for (channel, sample) in self.input_channels.iter_mut().zip(input) {
+                channel[frame] = *sample;
+            }
+        }
+        let sample = self
+            .synth
+            .sample()
+            .map(|id| &self.tape_model.tapes[id].audio);
+        if let [left, right] = &mut self.input_channels[input_channel_count..] {
+            self.synth.process(
+                sample,
+                [&mut left[..frame_count], &mut right[..frame_count]],
+            );
+        }
+
+        let group = self.recording_group();
+        let routes: [[usize; TAPE_CHANNELS]; TAPE_COUNT] =
+            std::array::from_fn(|id| self.input_routes[id].channels(input_channel_count));
+        for (side, bus) in self.input_bus.iter_mut().enumerate() {
+            bus[..frame_count].fill(0.0);
+            for id in (0..TAPE_COUNT).filter(|id| group[*id]) {
+                let channel = routes[id][side];
+                // A channel that more tapes record from is heard once
+                if (0..id).any(|other| group[other] && routes[other][side] == channel) {
+                    continue;
+                }
+                mixer::mix_into(
+                    &mut bus[..frame_count],
+                    &self.input_channels[channel][..frame_count],
+                    1.0,
+                );
             }
-            counter += 1;
         }
-        self.samples_for_graphs[id] = temp[0..SAMPLE_GRAPH_SIZE]
-            .try_into()
-            .expect("Error setting samples_for_graphs");
-        self.audio_message_producer
-            .try_push(ModulMessage::SamplesForGraphs(self.samples_for_graphs))
-            .unwrap();
     }
 
-    fn merge_tapes(&mut self) {
-        let primary_tape = self.primary_tape;
-        let secondary_tapes = self.secondary_tapes;
+    /// The primary tape and the record enabled tapes
+    fn recording_group(&self) -> [bool; TAPE_COUNT] {
+        let mut group = self.record_enabled;
+        group[self.primary_tape] = true;
+        group
+    }
 
-        for i in 0..TAPE_COUNT {
-            if secondary_tapes[i] && i != primary_tape {
-                self.tape_model.tapes[primary_tape].add(self.tape_model.tapes[i].audio.clone());
-            }
+    /// Position in the loop
+    fn audio_index(&self) -> usize {
+        (self.timeline.position() % self.tape_length as u64) as usize
+    }
+
+    /// Every tape has its own length, this is where it is playing right now
+    /// Multiplied and divided tapes stay in sync, their lengths divide each other
+    fn tape_index(&self, id: usize) -> usize {
+        (self.timeline.position() % self.tape_model.tapes[id].length() as u64) as usize
+    }
+
+    /// The capture buffer holds whole loops, so capture_index % tape_length == audio_index
+    fn capture_index(&self) -> usize {
+        (self.timeline.position() % self.capture_buffer.capacity() as u64) as usize
+    }
+
+    /// `offset` is the position in the block, `start` is the position in the loop
+    fn process_segment(
+        &mut self,
+        offset: usize,
+        start: usize,
+        len: usize,
+        gains: &[[f32; TAPE_COUNT]; TAPE_CHANNELS],
+    ) {
+        // Taken before recording, a free running recording can redefine the loop
+        let indices: [usize; TAPE_COUNT] = std::array::from_fn(|id| self.tape_index(id));
+        self.record_segment(offset, indices, len);
+        let capture_index = self.capture_index();
+        for (channel, input) in self.input_channels.iter().enumerate() {
+            self.capture_buffer
+                .write(channel, capture_index, &input[offset..offset + len]);
         }
 
-        self.update_waveform(
-            primary_tape,
-            &self.tape_model.tapes[primary_tape].audio.clone(),
+        let [left, right] = &mut self.master_bus;
+        self.tape_mixer.mix(
+            &self.tape_model.tapes,
+            indices,
+            gains,
+            [
+                &mut left[offset..offset + len],
+                &mut right[offset..offset + len],
+            ],
+            &mut self.sample_averages[..TAPE_COUNT],
         );
+
+        // Interleaved, it is written to a stereo file
+        // Stays within the preallocated capacity, also skipped while the worker writes it
+        let writable =
+            ((self.writing_tape.capacity() - self.writing_tape.len()) / TAPE_CHANNELS).min(len);
+        for frame in offset..offset + writable {
+            for channel in 0..TAPE_CHANNELS {
+                let mut sample = self.master_bus[channel][frame];
+                if self.is_recording_playback {
+                    sample += self.input_bus[channel][frame];
+                }
+                self.writing_tape.push(sample);
+            }
+        }
+
+        self.mix_cue(offset, len, self.output_settings.cue_tape_level);
+        self.play_through(offset, len);
+
+        // sine wave for metronome
+        let position = self.timeline.position();
+        self.click_bus[offset..offset + len].fill(0.0);
+        if self.metronome.is_running {
+            for i in 0..len {
+                self.metronome
+                    .update(self.timeline.beats(position + i as u64));
+                if self.metronome.show_beat() {
+                    #[allow(clippy::manual_is_multiple_of)]
+                    let first_beat = self.metronome.get_beat_index() % 4 == 0;
+                    let freq: f32 = if first_beat {
+                        utils::C_FREQ
+                    } else {
+                        utils::A_FREQ
+                    };
+                    let volume = 0.02;
+                    let t_index = start + i;
+                    self.click_bus[offset + i] =
+                        (t_index as f32 * 2.0 * std::f32::consts::PI * freq
+                            / self.sample_rate as f32)
+                            .sin()
+                            * volume;
+                }
+            }
+        } else {
+            self.metronome
+                .update(self.timeline.beats(position + len as u64));
+        }
+        // Without a cue mix the click has to be in the main mix, otherwise nobody hears it
+        let (bus, level) = match self.output_settings.cue_pair {
+            Some(_) => (&mut self.cue_bus, self.output_settings.cue_metronome_level),
+            None => (&mut self.master_bus, 1.0),
+        };
+        for channel in bus.iter_mut() {
+            mixer::mix_into(
+                &mut channel[offset..offset + len],
+                &self.click_bus[offset..offset + len],
+                level,
+            );
+        }
+        // ========
     }
 
-    fn check_user_input(&mut self) {
-        while !self.action_consumer.is_empty() {
-            let action = self.action_consumer.try_pop().unwrap();
-            match action {
-                ModulAction::SelectPrimaryTape(primary_tape) => {
-                    self.primary_tape = primary_tape.clamp(0, TAPE_COUNT);
+    /// The tapes stand still, only the input can be heard
+    fn process_stopped_segment(&mut self, offset: usize, len: usize) {
+        for channel in self.master_bus.iter_mut() {
+            channel[offset..offset + len].fill(0.0);
+        }
+        self.mix_cue(offset, len, 0.0);
+        self.play_through(offset, len);
+    }
+
+    /// The tapes and the input at their own levels, the master bus holds only the tapes at this point
+    fn mix_cue(&mut self, offset: usize, len: usize, tape_level: f32) {
+        let input_level = self.output_settings.cue_input_level;
+        for ((cue, master), input) in self
+            .cue_bus
+            .iter_mut()
+            .zip(&self.master_bus)
+            .zip(&self.input_bus)
+        {
+            let cue = &mut cue[offset..offset + len];
+            cue.fill(0.0);
+            mixer::mix_into(cue, &master[offset..offset + len], tape_level);
+            mixer::mix_into(cue, &input[offset..offset + len], input_level);
+        }
+    }
+
+    fn play_through(&mut self, offset: usize, len: usize) {
+        if !self.is_play_through {
+            return;
+        }
+        for (master, input) in self.master_bus.iter_mut().zip(&self.input_bus) {
+            let peak = mixer::mix_into(
+                &mut master[offset..offset + len],
+                &input[offset..offset + len],
+                1.0,
+            );
+            self.sample_averages[TAPE_COUNT] = f32::max(self.sample_averages[TAPE_COUNT], peak);
+        }
+    }
+
+    /// `indices` are the positions in the tapes
+    fn record_segment(&mut self, offset: usize, indices: [usize; TAPE_COUNT], len: usize) {
+        let mut record_from = 0;
+        if self.is_armed {
+            let threshold = db_to_gain(self.record_settings.threshold);
+            // Recording starts with the frame that crossed the threshold on any recorded channel
+            let crossed =
+                (offset..offset + len).position(|frame| self.is_over_threshold(frame, threshold));
+            record_from = crossed.unwrap_or(len);
+            for (pre_roll, input) in self.pre_roll.iter_mut().zip(&self.input_channels) {
+                pre_roll.push(&input[offset..offset + record_from]);
+            }
+            if crossed.is_some() {
+                self.trigger_recording(indices, record_from);
+            }
+        }
+
+        if !self.is_recording {
+            return;
+        }
+        let input_channel_count = self.input_channel_count;
+        if self.is_free_running {
+            let free_recording_length = self.free_recording_length;
+            let recording = &mut self.recordings[0];
+            let record_to = record_from
+                + (len - record_from).min(recording.audio[0].len() - free_recording_length);
+            let recorded = record_to - record_from;
+            let channels = self.input_routes[recording.id].channels(input_channel_count);
+            for (audio, channel) in recording.audio.iter_mut().zip(channels) {
+                audio[free_recording_length..free_recording_length + recorded].copy_from_slice(
+                    &self.input_channels[channel][offset + record_from..offset + record_to],
+                );
+            }
+            self.free_recording_length += recorded;
+            if self.free_recording_length == self.recordings[0].audio[0].len() {
+                self.stop_recording();
+                // The take filled up part way through the segment, the timeline still advances
+                // over the whole segment, so the loop has to start over `record_to` frames earlier
+                if !self.is_free_running {
+                    let tape_length = self.tape_length as u64;
+                    let position =
+                        self.timeline.position() + tape_length - record_to as u64 % tape_length;
+                    self.timeline.locate(position % tape_length);
                 }
-                ModulAction::SelectSecondaryTape(secondary_tape) => {
-                    self.secondary_tapes[secondary_tape] = !self.secondary_tapes[secondary_tape];
+            }
+            return;
+        }
+        let mut record_to = len;
+        if let Some(remaining) = self.recording_remaining {
+            record_to = record_from + remaining.min(len - record_from);
+        }
+        for recording in self.recordings.iter_mut() {
+            let start = indices[recording.id];
+            let channels = self.input_routes[recording.id].channels(input_channel_count);
+            for (audio, channel) in recording.audio.iter_mut().zip(channels) {
+                audio[start + record_from..start + record_to].copy_from_slice(
+                    &self.input_channels[channel][offset + record_from..offset + record_to],
+                );
+            }
+        }
+        if let Some(remaining) = self.recording_remaining.as_mut() {
+            *remaining -= record_to - record_from;
+            if *remaining == 0 {
+                self.stop_recording();
+            }
+        }
+    }
+
+    fn is_over_threshold(&self, frame: usize, threshold: f32) -> bool {
+        let group = self.recording_group();
+        (0..TAPE_COUNT).filter(|id| group[*id]).any(|id| {
+            self.input_routes[id]
+                .channels(self.input_channel_count)
+                .iter()
+                .any(|channel| self.input_channels[*channel][frame].abs() > threshold)
+        })
+    }
+
+    /// Starts an armed recording `record_from` frames after `indices` and writes the pre-roll right before it
+    fn trigger_recording(&mut self, indices: [usize; TAPE_COUNT], record_from: usize) {
+        let primary_length = self.tape_model.tapes[self.primary_tape].length();
+        self.is_armed = false;
+        self.send(ModulMessage::Armed(self.is_armed));
+        if !self.start_recording() {
+            return;
+        }
+
+        let pre_roll = self
+            .timeline
+            .position_of_seconds(self.record_settings.pre_roll.min(MAX_PRE_ROLL) as f64)
+            as usize;
+        let pre_roll = pre_roll
+            .min(self.pre_roll[0].available())
+            .min(primary_length);
+        let input_channel_count = self.input_channel_count;
+        if self.is_free_running {
+            // There is no loop yet, the free running recording simply starts with the pre-roll
+            let recording = &mut self.recordings[0];
+            let channels = self.input_routes[recording.id].channels(input_channel_count);
+            for (audio, channel) in recording.audio.iter_mut().zip(channels) {
+                for age in 1..=pre_roll {
+                    audio[pre_roll - age] = self.pre_roll[channel].get(age);
                 }
-                ModulAction::MergeTapes => {
-                    let message = format!(
-                        "Merging tapes, primary: {}, secondary: {:?}",
-                        self.primary_tape, self.secondary_tapes
-                    );
-                    self.log_producer.try_push(message).unwrap();
-                    self.merge_tapes();
+            }
+            self.free_recording_length = pre_roll;
+        } else {
+            for recording in self.recordings.iter_mut() {
+                let length = self.tape_model.tapes[recording.id].length();
+                let index = indices[recording.id] + record_from;
+                let channels = self.input_routes[recording.id].channels(input_channel_count);
+                for (audio, channel) in recording.audio.iter_mut().zip(channels) {
+                    for age in 1..=pre_roll.min(length) {
+                        audio[(index + length - age) % length] = self.pre_roll[channel].get(age);
+                    }
                 }
-                ModulAction::Record => {
-                    if self.is_recording {
-                        // TODO: Add this to settings
-                        // Stop recording to main tape also
-                        self.is_recording_playback = false;
-                        self.audio_message_producer
-                            .try_push(ModulMessage::RecordingPlayback(self.is_recording_playback))
-                            .unwrap();
-                        // ================================
-                        self.is_recording = false;
-                        self.audio_message_producer
-                            .try_push(ModulMessage::Recording(self.is_recording))
-                            .unwrap();
-
-                        let mut audio = vec![0.0; self.tape_length];
-
-                        for t in self.recording_tape.iter() {
-                            audio[t.index] = t.sample;
-                        }
-
-                        self.update_waveform(self.primary_tape, &audio);
-
-                        self.tape_model.tapes[self.primary_tape].audio = audio;
-                        self.recording_tape.clear();
+            }
+            // One loop including the pre-roll, so the attack is not overwritten at the end
+            if self.record_settings.is_one_loop {
+                self.recording_remaining = Some(primary_length - pre_roll);
+            }
+        }
+        self.clear_pre_roll();
+    }
+
+    fn clear_pre_roll(&mut self) {
+        for pre_roll in self.pre_roll.iter_mut() {
+            pre_roll.clear();
+        }
+    }
+
+    /// Takes a recording tape for the primary tape and every record enabled tape
+    fn start_recording(&mut self) -> bool {
+        // Free running recordings use a longer tape, that is only needed once
+        // and only the primary tape is recorded, it defines the loop
+        // Otherwise the recording tapes fit the longest multiplied tape
+        let mut group = self.recording_group();
+        if self.is_free_running {
+            group = [false; TAPE_COUNT];
+            group[self.primary_tape] = true;
+        }
+        let count = group.iter().filter(|is_recorded| **is_recorded).count();
+        if count > MAX_RECORDINGS {
+            self.log(LogMessage::TooManyRecordings);
+            return false;
+        }
+        let available = self.recording_tapes.len();
+        let is_ready = available >= count
+            && self.recording_tapes[available - count..]
+                .iter()
+                .all(|audio| {
+                    if self.is_free_running {
+                        !audio[0].is_empty()
                     } else {
-                        self.is_recording = true;
-                        self.audio_message_producer
-                            .try_push(ModulMessage::Recording(self.is_recording))
-                            .unwrap();
-                        self.recording_tape.clear();
+                        audio[0].len() == self.tape_length * MAX_LOOP_MULTIPLE
                     }
+                });
+        if !is_ready {
+            self.log(LogMessage::RecordingTapeNotReady);
+            return false;
+        }
+
+        let primary_tape = self.primary_tape;
+        let others = (0..TAPE_COUNT).filter(|id| group[*id] && *id != primary_tape);
+        for id in std::iter::once(primary_tape).chain(others) {
+            if let Some(audio) = self.recording_tapes.pop() {
+                self.recordings.push(Recording { id, audio });
+            }
+        }
+        self.is_recording = true;
+        self.recording_remaining = None;
+        let loop_count = self.record_settings.auto_advance_loop_count;
+        if self.record_settings.is_auto_advance && loop_count > 0 && !self.is_free_running {
+            let primary_length = self.tape_model.tapes[primary_tape].length();
+            self.recording_remaining = Some(primary_length * loop_count);
+        }
+        self.free_recording_length = 0;
+        self.send(ModulMessage::Recording(self.is_recording));
+
+        true
+    }
+
+    fn stop_recording(&mut self) {
+        // TODO: Add this to settings
+        // Stop recording to main tape also
+        self.is_recording_playback = false;
+        self.send(ModulMessage::RecordingPlayback(self.is_recording_playback));
+        // ================================
+        self.is_recording = false;
+        self.recording_remaining = None;
+        self.send(ModulMessage::Recording(self.is_recording));
+
+        if self.is_free_running {
+            self.define_loop();
+            self.is_advance_pending = self.record_settings.is_auto_advance && !self.is_free_running;
+            return;
+        }
+
+        // The worker sends a fresh recording tape back with every committed one
+        while let Some(Recording { id, audio }) = self.recordings.pop() {
+            let punch = self.punch_regions[id].map(|region| self.punch(region));
+            self.empty_tapes[id] = false;
+            self.send_job(Job::Commit { id, audio, punch });
+        }
+        self.is_advance_pending = self.record_settings.is_auto_advance;
+    }
+
+    /// Segments never cross the end of the loop, so a segment that starts at 0 is on the loop boundary
+    /// It also waits for recording tapes, right after the loop is defined they are still with the Worker
+    fn advance_recording(&mut self) {
+        if !self.is_advance_pending
+            || self.transport != TransportState::Playing
+            || self.audio_index() != 0
+            || self.recording_tapes.is_empty()
+        {
+            return;
+        }
+        self.is_advance_pending = false;
+        let next = (1..TAPE_COUNT)
+            .map(|i| (self.primary_tape + i) % TAPE_COUNT)
+            .find(|id| self.empty_tapes[*id]);
+        match next {
+            Some(id) => {
+                self.primary_tape = id;
+                self.send(ModulMessage::PrimaryTape(id));
+                let _ = self.start_recording();
+            }
+            None => self.log(LogMessage::NoEmptyTape),
+        }
+    }
+
+    /// Tape indices line up with the timeline, so beats from the start of the tape are timeline beats
+    fn punch(&self, region: PunchRegion) -> Punch {
+        Punch {
+            start: self.timeline.position_of_beats(region.start) as usize,
+            end: self.timeline.position_of_beats(region.end) as usize,
+            fade_length: self.timeline.position_of_seconds(PUNCH_FADE_LENGTH as f64) as usize,
+        }
+    }
+
+    /// The free running recording becomes the loop, its length sets the tempo
+    /// The take is cut to whole beats, so the loop started over a few frames before it ended
+    fn define_loop(&mut self) {
+        let beat_count = self.bar_count * 4;
+        // Whole frames for every beat, so bars and beats stay on the sample grid
+        let tape_length = self.free_recording_length - self.free_recording_length % beat_count;
+        if tape_length == 0 {
+            // Too short to be a loop, the recording tape can be used again
+            while let Some(recording) = self.recordings.pop() {
+                self.recording_tapes.push(recording.audio);
+            }
+            return;
+        }
+
+        let bpm = self.timeline.bpm_for_length(tape_length, beat_count);
+        self.is_free_running = false;
+        self.tape_length = tape_length;
+        self.timeline.set_tempo(bpm);
+        self.timeline
+            .locate((self.free_recording_length - tape_length) as u64);
+        self.send(ModulMessage::LoopDefined { tape_length, bpm });
+        self.log(LogMessage::LoopDefined { bpm });
+
+        if let Some(Recording { id, audio }) = self.recordings.pop() {
+            self.send_job(Job::DefineLoop {
+                id,
+                audio,
+                tape_length,
+            });
+        }
+    }
+
+    /// UI messages are dropped if the UI is busy, blocking here is not an option
+    fn send(&mut self, message: ModulMessage) {
+        let _ = self.audio_message_producer.try_push(message);
+    }
+
+    fn log(&mut self, message: LogMessage) {
+        self.send(ModulMessage::Log(message));
+    }
+
+    /// Jobs keep their order, a job waits if an earlier one is still pending
+    fn send_job(&mut self, job: Job) {
+        if !self.pending_jobs.is_empty() {
+            self.pending_jobs.push_back(job);
+            return;
+        }
+        if let Err(job) = self.job_producer.try_push(job) {
+            self.log(LogMessage::WorkerIsBusy);
+            self.pending_jobs.push_back(job);
+        }
+    }
+
+    fn send_pending_jobs(&mut self) {
+        while let Some(job) = self.pending_jobs.pop_front() {
+            if let Err(job) = self.job_producer.try_push(job) {
+                self.pending_jobs.push_front(job);
+                return;
+            }
+        }
+    }
+
+    /// Swaps in the buffers the worker has finished, the old ones go back to be freed
+    fn receive_done(&mut self) {
+        while let Some(done) = self.done_consumer.try_pop() {
+            match done {
+                Done::Tape {
+                    id,
+                    mut audio,
+                    is_empty,
+                } => {
+                    std::mem::swap(&mut self.tape_model.tapes[id].audio, &mut audio);
+                    self.empty_tapes[id] = is_empty;
+                    self.send_job(Job::Recycle(audio));
                 }
-                ModulAction::RecordPlayback => {
-                    self.is_recording_playback = !self.is_recording_playback;
-                    self.audio_message_producer
-                        .try_push(ModulMessage::RecordingPlayback(self.is_recording_playback))
-                        .unwrap();
+                Done::RecordingTape(audio) => self.recording_tapes.push(audio),
+                Done::WritingTape(audio) => self.writing_tape = audio,
+                Done::Song(song) => self.song = Some(song),
+                Done::CaptureBuffer(capture_buffer) => {
+                    let capture_buffer =
+                        std::mem::replace(&mut self.capture_buffer, capture_buffer);
+                    self.send_job(Job::RecycleCaptureBuffer(capture_buffer));
                 }
-                ModulAction::PlayThrough => {
-                    self.is_play_through = !self.is_play_through;
-                    self.audio_message_producer
-                        .try_push(ModulMessage::PlayThrough(self.is_play_through))
-                        .unwrap();
+            }
+        }
+    }
+
+    /// Actions wait in `scheduled_actions` until the mixing loop gets to their frame
+    /// While the Worker is behind or too many actions are scheduled, they wait in the action queue
+    fn check_user_input(&mut self) {
+        while self.pending_jobs.is_empty() && self.scheduled_actions.len() < MAX_SCHEDULED_ACTIONS {
+            let Some(scheduled) = self.action_consumer.try_pop() else {
+                break;
+            };
+            let frame = self.action_frame(scheduled.timestamp);
+            // Actions on the same frame are applied in the order they came in
+            let index = self
+                .scheduled_actions
+                .partition_point(|pending| pending.frame <= frame);
+            self.scheduled_actions.insert(
+                index,
+                PendingAction {
+                    frame,
+                    action: scheduled.action,
+                },
+            );
+        }
+    }
+
+    fn action_frame(&self, timestamp: Timestamp) -> u64 {
+        match timestamp {
+            Timestamp::Now => self.frame_position,
+            // Frames in the past are applied right away
+            Timestamp::Frame(frame) => frame.max(self.frame_position),
+            // The loop doesn't move, so there is nothing to wait for
+            _ if self.transport != TransportState::Playing => self.frame_position,
+            Timestamp::NextBeat => self.frame_position + self.frames_to_next(1.0),
+            Timestamp::NextBar => self.frame_position + self.frames_to_next(BEATS_PER_BAR as f64),
+            Timestamp::Musical { bar, beat } => {
+                self.frame_position + self.frames_to_musical(bar, beat)
+            }
+        }
+    }
+
+    /// Frames until the loop gets to `bar` and `beat` again, it follows the tempo map
+    fn frames_to_musical(&self, bar: usize, beat: usize) -> u64 {
+        let position = self.timeline.position();
+        let loop_beats = (self.bar_count as u64 * BEATS_PER_BAR) as f64;
+        let beats = self.timeline.beats(position);
+        let target = (bar as u64 * BEATS_PER_BAR + beat as u64) as f64 % loop_beats;
+        let mut next = (beats / loop_beats).floor() * loop_beats + target;
+        if next < beats - 1e-6 {
+            next += loop_beats;
+        }
+        self.timeline
+            .position_of_beats(next)
+            .saturating_sub(position)
+    }
+
+    /// Frames until the timeline gets to the next multiple of `beats`, it follows the tempo map
+    /// A position right on the grid is already there
+    fn frames_to_next(&self, beats: f64) -> u64 {
+        let position = self.timeline.position();
+        let steps = (self.timeline.beats(position) / beats - 1e-6).ceil();
+        self.timeline
+            .position_of_beats(steps * beats)
+            .saturating_sub(position)
+    }
+
+    fn apply_due_actions(&mut self) {
+        while self
+            .scheduled_actions
+            .first()
+            .is_some_and(|pending| pending.frame <= self.frame_position)
+        {
+            let pending = self.scheduled_actions.remove(0);
+            self.apply_action(pending.action);
+        }
+    }
+
+    /// Recording and arming end with the transport, the recording is committed
+    fn set_transport(&mut self, transport: TransportState) {
+        if transport != TransportState::Playing {
+            if self.is_recording {
+                self.stop_recording();
+            }
+            self.is_advance_pending = false;
+            self.is_latched = [false; TAPE_COUNT];
+            self.is_armed = false;
+            self.send(ModulMessage::Armed(self.is_armed));
+        }
+        self.transport = transport;
+    }
+
+    /// Tapes, capture buffer and metronome follow, their positions come from the timeline
+    fn locate(&mut self, bar: usize) {
+        self.timeline.locate(self.timeline.bar_position(bar));
+    }
+
+    fn apply_action(&mut self, action: ModulAction) {
+        match action {
+            ModulAction::SelectPrimaryTape(primary_tape) => {
+                self.primary_tape = primary_tape.clamp(0, TAPE_COUNT);
+                self.send(ModulMessage::PrimaryTape(self.primary_tape));
+            }
+            ModulAction::SelectSecondaryTape(secondary_tape) => {
+                self.secondary_tapes[secondary_tape] = !self.secondary_tapes[secondary_tape];
+            }
+            ModulAction::MergeTapes => {
+                self.log(LogMessage::MergingTapes {
+                    primary_tape: self.primary_tape,
+                    secondary_tapes: self.secondary_tapes,
+                });
+                self.send_job(Job::Merge {
+                    primary_tape: self.primary_tape,
+                    secondary_tapes: self.secondary_tapes,
+                });
+            }
+            ModulAction::Record => {
+                if self.is_recording {
+                    self.stop_recording();
+                } else if self.is_advance_pending {
+                    // Pressing record again before the next tape starts ends the auto-advance
+                    self.is_advance_pending = false;
+                } else {
+                    self.is_armed = false;
+                    self.send(ModulMessage::Armed(self.is_armed));
+                    let _ = self.start_recording();
                 }
-                ModulAction::Write => {
-                    // let tape = merge_tapes(&self.tape_model.tapes);
-                    // write_tape(&tape, "test");
-                    write(&self.writing_tape, "full");
+            }
+            ModulAction::ArmRecord => {
+                if !self.is_recording {
+                    self.is_armed = !self.is_armed;
+                    self.clear_pre_roll();
+                    self.send(ModulMessage::Armed(self.is_armed));
                 }
-                ModulAction::Clear => {
-                    self.log_producer
-                        .try_push(format!("Cleared tape {}", self.primary_tape + 1))
-                        .unwrap();
-                    self.tape_model.tapes[self.primary_tape].clear(0.0);
-                    self.update_waveform(self.primary_tape, &vec![0.0; SAMPLE_GRAPH_SIZE]);
+            }
+            ModulAction::Capture(bar_count) => {
+                let bar_length = self.tape_length / self.bar_count;
+                // Only whole bars, the bar that is playing right now is left out
+                let capacity = self.capture_buffer.capacity();
+                let end =
+                    (self.capture_index() + capacity - self.audio_index() % bar_length) % capacity;
+                let len = (bar_count * bar_length).min(self.tape_length);
+                self.log(LogMessage::Captured {
+                    id: self.primary_tape,
+                    bar_count,
+                });
+                self.send_job(Job::Capture {
+                    id: self.primary_tape,
+                    end,
+                    len,
+                    route: self.input_routes[self.primary_tape],
+                });
+            }
+            ModulAction::MultiplyTape(factor) => {
+                self.send_job(Job::Multiply {
+                    id: self.primary_tape,
+                    factor,
+                });
+            }
+            ModulAction::DivideTape(divisor) => {
+                self.send_job(Job::Divide {
+                    id: self.primary_tape,
+                    divisor,
+                });
+            }
+            ModulAction::SetRecordSettings(record_settings) => {
+                self.record_settings = record_settings;
+            }
+            ModulAction::RecordPlayback => {
+                self.is_recording_playback = !self.is_recording_playback;
+                self.send(ModulMessage::RecordingPlayback(self.is_recording_playback));
+            }
+            ModulAction::PlayThrough => {
+                self.is_play_through = !self.is_play_through;
+                self.send(ModulMessage::PlayThrough(self.is_play_through));
+            }
+            ModulAction::Write => {
+                if self.writing_tape.capacity() == 0 {
+                    self.log(LogMessage::WritingTapeNotReady);
+                } else {
+                    let writing_tape = std::mem::take(&mut self.writing_tape);
+                    self.send_job(Job::Write(writing_tape));
                 }
-                ModulAction::ClearAll => {
-                    self.log_producer
-                        .try_push("Cleared all tapes".to_owned())
-                        .unwrap();
-                    for id in 0..TAPE_COUNT {
-                        self.tape_model.tapes[id].clear(0.0);
-                        self.update_waveform(id, &vec![0.0; SAMPLE_GRAPH_SIZE]);
+            }
+            ModulAction::Clear => {
+                self.log(LogMessage::Cleared(self.primary_tape));
+                self.send_job(Job::Clear(self.primary_tape));
+            }
+            ModulAction::ClearAll => {
+                self.log(LogMessage::ClearedAll);
+                self.send_job(Job::ClearAll);
+            }
+            ModulAction::ToggleMute => {
+                self.touch(self.primary_tape);
+                self.tape_model.tapes[self.primary_tape].toggle_mute();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.touch(i);
+                        self.tape_model.tapes[i].toggle_mute();
+                    }
+                }
+            }
+            ModulAction::ToggleSolo => {
+                self.tape_model.tapes[self.primary_tape].toggle_solo();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.tape_model.tapes[i].toggle_solo();
+                    }
+                }
+            }
+            ModulAction::VolumeUp => {
+                self.touch(self.primary_tape);
+                self.tape_model.tapes[self.primary_tape].volume_up();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.touch(i);
+                        self.tape_model.tapes[i].volume_up();
                     }
                 }
-                ModulAction::ToggleMute => {
-                    self.tape_model.tapes[self.primary_tape].toggle_mute();
-                    for i in 0..TAPE_COUNT {
-                        if self.secondary_tapes[i] {
-                            self.tape_model.tapes[i].toggle_mute();
-                        }
+            }
+            ModulAction::VolumeDown => {
+                self.touch(self.primary_tape);
+                self.tape_model.tapes[self.primary_tape].volume_down();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.touch(i);
+                        self.tape_model.tapes[i].volume_down();
                     }
                 }
-                ModulAction::ToggleSolo => {
-                    self.tape_model.tapes[self.primary_tape].toggle_solo();
-                    for i in 0..TAPE_COUNT {
-                        if self.secondary_tapes[i] {
-                            self.tape_model.tapes[i].toggle_solo();
-                        }
+            }
+            ModulAction::PanLeft => {
+                self.tape_model.tapes[self.primary_tape].pan_left();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.tape_model.tapes[i].pan_left();
                     }
                 }
-                ModulAction::VolumeUp => {
-                    self.tape_model.tapes[self.primary_tape].volume_up();
-                    for i in 0..TAPE_COUNT {
-                        if self.secondary_tapes[i] {
-                            self.tape_model.tapes[i].volume_up();
-                        }
+            }
+            ModulAction::PanRight => {
+                self.tape_model.tapes[self.primary_tape].pan_right();
+                for i in 0..TAPE_COUNT {
+                    if self.secondary_tapes[i] {
+                        self.tape_model.tapes[i].pan_right();
                     }
                 }
-                ModulAction::VolumeDown => {
-                    self.tape_model.tapes[self.primary_tape].volume_down();
-                    for i in 0..TAPE_COUNT {
-                        if self.secondary_tapes[i] {
-                            self.tape_model.tapes[i].volume_down();
-                        }
+            }
+            ModulAction::StartMetronome => {
+                self.metronome.is_running = true;
+            }
+            ModulAction::StopMetronome => {
+                self.metronome.is_running = false;
+            }
+            ModulAction::SetInputSettings(settings) => {
+                self.input_strip.set_settings(settings);
+            }
+            ModulAction::SetOutputSettings(output_settings) => {
+                self.output_settings = output_settings;
+            }
+            ModulAction::StoreScene(index) => {
+                self.scenes[index] = Some(std::array::from_fn(|id| {
+                    self.tape_model.tapes[id].get_mix()
+                }));
+                self.log(LogMessage::SceneStored(index));
+            }
+            ModulAction::RecallScene { index, fade_time } => match self.scenes[index] {
+                Some(mixes) => {
+                    let length = self.timeline.position_of_seconds(fade_time as f64) as usize;
+                    // Starts from where a fade that is still running has got to
+                    self.scene_fade = (length > 0).then_some(SceneFade {
+                        from: self.gains,
+                        length,
+                        position: 0,
+                    });
+                    for (tape, mix) in self.tape_model.tapes.iter_mut().zip(mixes) {
+                        tape.set_mix(mix);
                     }
+                    self.log(LogMessage::SceneRecalled(index));
                 }
-                ModulAction::StartMetronome => {
-                    self.metronome.is_running = true;
+                None => self.log(LogMessage::SceneIsEmpty(index)),
+            },
+            ModulAction::SetAutomationMode { id, mode } => {
+                self.automation_modes[id] = mode;
+                self.touch_remaining[id] = 0;
+                self.is_latched[id] = false;
+            }
+            ModulAction::ClearAutomation(id) => {
+                self.automation_lanes[id].clear();
+                self.is_automation_changed[id] = true;
+            }
+            ModulAction::SetSynth(settings) => self.synth.set_settings(settings),
+            ModulAction::NoteOn { note, velocity } => self.synth.note_on(note, velocity),
+            ModulAction::NoteOff(note) => self.synth.note_off(note),
+            ModulAction::SetDucker { target, settings } => {
+                self.tape_mixer.set_ducker(target, settings);
+            }
+            ModulAction::SetModulation(mut modulation) => {
+                std::mem::swap(&mut self.tape_mixer.modulation, &mut modulation);
+                self.send_job(Job::RecycleModulation(modulation));
+            }
+            ModulAction::SetArrangement(mut arrangement) => {
+                std::mem::swap(&mut self.arrangement, &mut arrangement);
+                self.send_job(Job::RecycleArrangement(arrangement));
+            }
+            ModulAction::SetSongMode(is_song_mode) => {
+                self.is_song_mode = is_song_mode;
+                self.section = None;
+                // The song is played from the start
+                if is_song_mode {
+                    self.locate(0);
+                    self.set_transport(TransportState::Playing);
                 }
-                ModulAction::StopMetronome => {
-                    self.metronome.is_running = false;
+            }
+            ModulAction::RenderArrangement { with_stems } => {
+                if self.arrangement.sections().is_empty() {
+                    self.log(LogMessage::ArrangementIsEmpty);
+                } else if let Some(mut song) = self.song.take() {
+                    song.arrangement = *self.arrangement;
+                    song.mixes = std::array::from_fn(|id| self.tape_model.tapes[id].get_mix());
+                    song.scenes = self.scenes;
+                    song.bar_length = self.tape_length / self.bar_count;
+                    song.loop_length = self.tape_length;
+                    song.automation_lanes = self.automation_lanes;
+                    song.automation_modes = self.automation_modes;
+                    song.modulation = *self.tape_mixer.modulation;
+                    song.duckers = self.tape_mixer.ducker_settings();
+                    song.with_stems = with_stems;
+                    self.send_job(Job::Render(song));
+                } else {
+                    self.log(LogMessage::WritingTapeNotReady);
                 }
             }
+            ModulAction::SelectTake { id, number } => {
+                self.send_job(Job::SelectTake { id, number });
+            }
+            ModulAction::FlattenComp { id, comp } => {
+                let fade_length = self.timeline.position_of_seconds(PUNCH_FADE_LENGTH as f64);
+                self.send_job(Job::Flatten {
+                    id,
+                    comp,
+                    bar_length: self.tape_length / self.bar_count,
+                    fade_length: fade_length as usize,
+                });
+            }
+            ModulAction::SetPunchRegion { id, region } => {
+                self.punch_regions[id] = region;
+            }
+            ModulAction::SetInputRoute { id, route } => {
+                self.input_routes[id] = route;
+            }
+            ModulAction::ToggleRecordEnabled(id) => {
+                self.record_enabled[id] = !self.record_enabled[id];
+            }
+            ModulAction::Play => self.set_transport(TransportState::Playing),
+            ModulAction::Pause => self.set_transport(TransportState::Paused),
+            ModulAction::Stop => {
+                self.set_transport(TransportState::Stopped);
+                self.locate(0);
+            }
+            ModulAction::Locate(bar) => self.locate(bar),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::{
+        DuckerSettings, ModulationRoute, ModulationSettings, ModulationSource, ModulationTarget,
+    };
+    use assert_no_alloc::assert_no_alloc;
+    use ringbuf::HeapRb;
+
+    const SAMPLE_RATE: u32 = 8000;
+    const CHANNEL_COUNT: usize = 2;
+    const BUFFER_FRAMES: usize = 256;
+
+    /// An AudioModel and its Worker, fed like the streams would feed them
+    /// The Worker handles its jobs after every buffer instead of on its own thread
+    struct Streams {
+        audio_model: AudioModel,
+        worker: Worker,
+        input_producer: HeapProd<f32>,
+        action_producer: HeapProd<ScheduledAction>,
+        message_consumer: HeapCons<ModulMessage>,
+        output: Vec<f32>,
+        messages: Vec<ModulMessage>,
+    }
+
+    impl Streams {
+        fn new() -> Self {
+            Self::with_config(Config {
+                bpm: 120,
+                bar_count: 1,
+                is_free_running: false,
+            })
+        }
+
+        fn with_config(config: Config) -> Self {
+            let mut timeline = Timeline::new(SAMPLE_RATE, config.bpm as f64);
+            let tape_length = timeline.position_of_beats(4.0) as usize;
+            timeline.set_tempo(timeline.bpm_for_length(tape_length, 4));
+
+            let (input_producer, input_consumer) =
+                HeapRb::<f32>::new(BUFFER_FRAMES * CHANNEL_COUNT * 4).split();
+            let (action_producer, action_consumer) =
+                HeapRb::<ScheduledAction>::new(ACTION_QUEUE_SIZE).split();
+            let (audio_message_producer, message_consumer) =
+                HeapRb::<ModulMessage>::new(2_usize.pow(10)).split();
+            let (worker_message_producer, _) = HeapRb::<ModulMessage>::new(2_usize.pow(6)).split();
+            let (job_producer, job_consumer) = HeapRb::<Job>::new(JOB_QUEUE_SIZE).split();
+            let (done_producer, done_consumer) = HeapRb::<Done>::new(2_usize.pow(6)).split();
+            let capture_buffer = CaptureBuffer::new(
+                CHANNEL_COUNT + TAPE_CHANNELS,
+                tape_length * CAPTURE_LOOP_COUNT,
+            );
+            let worker = Worker::new(
+                tape_length,
+                SAMPLE_RATE,
+                capture_buffer.clone(),
+                job_consumer,
+                done_producer,
+                worker_message_producer,
+            );
+
+            let format = StreamFormat {
+                input_sample_rate: SAMPLE_RATE,
+                output_sample_rate: SAMPLE_RATE,
+                input_channel_count: CHANNEL_COUNT,
+                output_channel_count: CHANNEL_COUNT,
+            };
+            let queues = AudioQueues {
+                input_consumer,
+                action_consumer,
+                audio_message_producer,
+                job_producer,
+                done_consumer,
+            };
+            Self {
+                audio_model: AudioModel::new(
+                    &config,
+                    timeline,
+                    tape_length,
+                    format,
+                    capture_buffer,
+                    queues,
+                ),
+                worker,
+                input_producer,
+                action_producer,
+                message_consumer,
+                output: vec![0.0; BUFFER_FRAMES * CHANNEL_COUNT],
+                messages: Vec::new(),
+            }
+        }
+
+        fn schedule(&mut self, action: ModulAction, timestamp: Timestamp) {
+            let scheduled = ScheduledAction { action, timestamp };
+            assert!(self.action_producer.try_push(scheduled).is_ok());
+        }
+
+        /// Only the output callback runs without allocating, in the app the input, the messages
+        /// and the Worker are on other threads
+        fn play(&mut self, buffer_count: usize) {
+            for _ in 0..buffer_count {
+                let input = (0..BUFFER_FRAMES * CHANNEL_COUNT).map(|i| (i as f32 * 0.05).sin());
+                self.input_producer.push_iter(input);
+                assert_no_alloc(|| self.audio_model.process(&mut self.output));
+                self.messages.extend(self.message_consumer.pop_iter());
+                self.worker.handle_jobs();
+            }
+        }
+
+        fn has_logged(&self, log_message: fn(&LogMessage) -> bool) -> bool {
+            self.messages.iter().any(|message| match message {
+                ModulMessage::Log(message) => log_message(message),
+                _ => false,
+            })
+        }
+    }
+
+    #[test]
+    fn process_does_not_allocate() {
+        let mut streams = Streams::new();
+        let mut modulation = ModulationSettings::default();
+        for (source, target) in [
+            (ModulationSource::Lfo(0), ModulationTarget::Volume),
+            (ModulationSource::Tape(1), ModulationTarget::Pan),
+            (ModulationSource::Input, ModulationTarget::Cutoff),
+            (ModulationSource::Lfo(1), ModulationTarget::Rate),
+        ] {
+            modulation.push(ModulationRoute {
+                source,
+                tape: 0,
+                target,
+                depth: 0.5,
+            });
+        }
+        let ducker = DuckerSettings {
+            key: Some(0),
+            threshold: -60.0,
+            ..DuckerSettings::default()
+        };
+        for action in [
+            ModulAction::SetInputRoute {
+                id: 0,
+                route: InputRoute::Synth,
+            },
+            ModulAction::NoteOn {
+                note: 60,
+                velocity: 1.0,
+            },
+            ModulAction::SetAutomationMode {
+                id: 0,
+                mode: AutomationMode::Overwrite,
+            },
+            ModulAction::SetModulation(Box::new(modulation)),
+            ModulAction::SetDucker {
+                target: 1,
+                settings: ducker,
+            },
+            ModulAction::SetDucker {
+                target: TAPE_COUNT,
+                settings: ducker,
+            },
+            ModulAction::StartMetronome,
+            ModulAction::Record,
+        ] {
+            streams.schedule(action, Timestamp::Now);
         }
+        streams.play(20);
+
+        // The recording is committed by the Worker and the tape comes back
+        streams.schedule(ModulAction::Record, Timestamp::NextBeat);
+        streams.schedule(ModulAction::NoteOff(60), Timestamp::NextBeat);
+        streams.play(20);
+        assert!(!streams.audio_model.empty_tapes[0]);
+
+        streams.schedule(ModulAction::StoreScene(0), Timestamp::Now);
+        streams.schedule(ModulAction::ToggleMute, Timestamp::NextBeat);
+        streams.schedule(
+            ModulAction::RecallScene {
+                index: 0,
+                fade_time: 0.1,
+            },
+            Timestamp::NextBar,
+        );
+        streams.play(100);
+
+        assert!(streams.has_logged(|message| matches!(message, LogMessage::SceneRecalled(0))));
+        assert!(streams.messages.iter().any(|message| matches!(
+            message,
+            ModulMessage::GainReductions(reductions) if reductions[1] > 0.0
+        )));
+    }
+
+    #[test]
+    fn frame_actions_are_applied_on_their_sample() {
+        let mut streams = Streams::new();
+        // The timeline only moves while playing, so it shows where Play was applied
+        let frame = (BUFFER_FRAMES * 3 + 77) as u64;
+        streams.schedule(ModulAction::Pause, Timestamp::Now);
+        streams.schedule(ModulAction::Play, Timestamp::Frame(frame));
+        streams.play(2);
+        assert_eq!(streams.audio_model.timeline.position(), 0);
+
+        streams.play(3);
+        let played = (BUFFER_FRAMES * 5) as u64 - frame;
+        assert_eq!(streams.audio_model.timeline.position(), played);
+
+        // A frame that has passed is applied at the start of the next buffer
+        streams.schedule(ModulAction::Pause, Timestamp::Frame(frame));
+        streams.play(1);
+        assert_eq!(streams.audio_model.timeline.position(), played);
+    }
+
+    #[test]
+    fn musical_actions_are_applied_on_their_beat() {
+        let mut streams = Streams::new();
+        streams.play(1);
+        streams.schedule(ModulAction::Pause, Timestamp::Musical { bar: 0, beat: 2 });
+        streams.play(40);
+        let timeline = &streams.audio_model.timeline;
+        assert_eq!(timeline.position(), timeline.position_of_beats(2.0));
+    }
+
+    #[test]
+    fn a_free_running_loop_starts_where_its_take_started() {
+        let mut streams = Streams::with_config(Config {
+            bpm: 120,
+            bar_count: 1,
+            is_free_running: true,
+        });
+        // The take is 3 frames longer than whole beats, the loop started over before it ended
+        let start = 77;
+        let tape_length = BUFFER_FRAMES * 100;
+        streams.schedule(ModulAction::Record, Timestamp::Frame(start as u64));
+        streams.schedule(
+            ModulAction::Record,
+            Timestamp::Frame((start + tape_length + 3) as u64),
+        );
+        streams.play(120);
+        let audio_model = &streams.audio_model;
+        assert_eq!(audio_model.tape_length, tape_length);
+        let played = BUFFER_FRAMES * 120 - start;
+        assert_eq!(audio_model.audio_index(), played % tape_length);
+    }
+
+    #[test]
+    fn a_full_free_running_take_becomes_the_loop() {
+        let mut streams = Streams::with_config(Config {
+            bpm: 120,
+            bar_count: 1,
+            is_free_running: true,
+        });
+        // The take fills up in the middle of a buffer
+        let start = 77;
+        let capacity = (MAX_FREE_LOOP_LENGTH * SAMPLE_RATE as f32) as usize;
+        streams.schedule(ModulAction::Record, Timestamp::Frame(start as u64));
+        let buffer_count = (start + capacity) / BUFFER_FRAMES + 2;
+        streams.play(buffer_count);
+        let audio_model = &streams.audio_model;
+        assert!(!audio_model.is_recording);
+        assert_eq!(audio_model.tape_length, capacity);
+        let played = BUFFER_FRAMES * buffer_count - start;
+        assert_eq!(audio_model.audio_index(), played % capacity);
     }
 }
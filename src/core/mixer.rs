@@ -0,0 +1,106 @@
+//! Block mixing, everything in here works on whole blocks of samples
+//! Kept free of other modul types so the benchmarks can include it as is
+
+/// Samples that are processed together, the loops below are written so that
+/// the compiler turns each lane array into a single vector instruction
+pub const LANES: usize = 8;
+
+/// Resolves solo and mute into a single gain per tape, once per block
+/// Volumes are expected to be zero for muted tapes
+pub fn tape_gains(volumes: &[f32], solos: &[bool], gains: &mut [f32]) {
+    let is_there_any_solo = solos.iter().any(|solo| *solo);
+    for ((gain, volume), solo) in gains.iter_mut().zip(volumes).zip(solos) {
+        *gain = if !is_there_any_solo || *solo {
+            *volume
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Equal-power pan law, `pan` goes from -1 (left) to 1 (right)
+/// Both sides are at -3 dB in the center, so the loudness stays the same while panning
+pub fn pan_gains(pan: f32) -> [f32; 2] {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    [angle.cos(), angle.sin()]
+}
+
+/// Resolves solo, volume and pan of every tape into a gain for each side
+/// Volumes are expected to be zero for muted tapes
+pub fn mix_gains<const N: usize>(
+    volumes: &[f32; N],
+    solos: &[bool; N],
+    pans: &[f32; N],
+) -> [[f32; N]; 2] {
+    let mut tape_gains = [0.0; N];
+    self::tape_gains(volumes, solos, &mut tape_gains);
+    let mut gains = [[0.0; N]; 2];
+    for (id, (gain, pan)) in tape_gains.iter().zip(pans).enumerate() {
+        for (side, pan_gain) in gains.iter_mut().zip(pan_gains(*pan)) {
+            side[id] = gain * pan_gain;
+        }
+    }
+    gains
+}
+
+/// Adds `input * gain` to `output` and returns the peak of the scaled input, negative samples included
+pub fn mix_into(output: &mut [f32], input: &[f32], gain: f32) -> f32 {
+    let mut peaks = [0.0; LANES];
+    let mut output_chunks = output.chunks_exact_mut(LANES);
+    let mut input_chunks = input.chunks_exact(LANES);
+    for (output_chunk, input_chunk) in (&mut output_chunks).zip(&mut input_chunks) {
+        for lane in 0..LANES {
+            let sample = input_chunk[lane] * gain;
+            output_chunk[lane] += sample;
+            peaks[lane] = f32::max(peaks[lane], sample.abs());
+        }
+    }
+
+    let mut peak = peaks.iter().fold(0.0, |peak, lane| f32::max(peak, *lane));
+    for (output, input) in output_chunks
+        .into_remainder()
+        .iter_mut()
+        .zip(input_chunks.remainder())
+    {
+        let sample = input * gain;
+        *output += sample;
+        peak = f32::max(peak, sample.abs());
+    }
+
+    peak
+}
+
+/// Sums every tape with a non-zero gain into `output`, tapes are already
+/// sliced to the block that is being mixed
+pub fn mix_tapes(output: &mut [f32], tapes: &[&[f32]], gains: &[f32], peaks: &mut [f32]) {
+    output.fill(0.0);
+    for ((tape, gain), peak) in tapes.iter().zip(gains).zip(peaks.iter_mut()) {
+        if *gain != 0.0 {
+            *peak = f32::max(*peak, mix_into(output, tape, *gain));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // The benchmarks include this file without its tests, so nothing is imported here
+    #[test]
+    fn negative_samples_are_peaks() {
+        // Long enough for a whole lane chunk and a remainder
+        let input: Vec<f32> = (0..super::LANES + 3).map(|i| -(i as f32) / 20.0).collect();
+        let mut output = vec![0.0; input.len()];
+        let peak = super::mix_into(&mut output, &input, 0.5);
+        assert_eq!(peak, (super::LANES + 2) as f32 / 40.0);
+
+        let peak = super::mix_into(&mut output, &input[..super::LANES], 0.5);
+        assert_eq!(peak, (super::LANES - 1) as f32 / 40.0);
+    }
+
+    #[test]
+    fn negative_gains_are_peaks() {
+        let input = [0.5; super::LANES];
+        let mut output = [0.0; super::LANES];
+        assert_eq!(super::mix_into(&mut output, &input, -1.0), 0.5);
+        assert_eq!(output, [-0.5; super::LANES]);
+    }
+}
@@ -1,9 +1,17 @@
 mod audio_model;
+pub mod mixer;
 mod modul;
+mod tape_mixer;
+mod timeline;
 mod utils;
 mod view_time;
+mod worker;
 
 pub use audio_model::*;
 pub use modul::*;
+pub use tape_mixer::*;
+pub use timeline::*;
 pub use utils::*;
+#[allow(unused_imports)]
 pub use view_time::*;
+pub use worker::*;
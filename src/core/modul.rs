@@ -1,24 +1,33 @@
 use super::super::Config;
 use super::audio_model::*;
+use super::timeline::*;
 use super::utils::*;
-use crate::features::Metronome;
+use super::worker::*;
+use crate::features::{
+    Arrangement, AutomationMode, CaptureBuffer, DuckerSettings, InputSettings, ModulationSettings,
+    SynthSettings, TapeMix,
+};
+use assert_no_alloc::assert_no_alloc;
 use cpal::traits::StreamTrait;
 use cpal::traits::{DeviceTrait, HostTrait};
-use cpal::SampleRate;
+use cpal::SampleFormat;
 use cpal::{BufferSize, Stream, StreamConfig};
 use ringbuf::{traits::*, HeapCons, HeapProd, HeapRb};
 use std::collections::VecDeque;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub struct Stats {
     pub fps: f32,
-    pub bpm: u16,
+    pub bpm: f32,
     pub bar_count: usize,
     pub bar_length: f32,
     pub input_device_name: String,
     pub input_channel_count: u16,
+    pub input_sample_format: SampleFormat,
     pub input_buffer_size: u32,
     pub output_channel_count: u16,
+    pub output_sample_format: SampleFormat,
     pub output_buffer_size: u32,
     pub output_device_name: String,
 }
@@ -28,20 +37,48 @@ pub struct Modul {
     _input_stream: Stream,
     _output_stream: Stream,
     _time: f32,
-    audio_index: usize,
-    action_producer: HeapProd<ModulAction>,
-    _modul_message_producer: HeapProd<ModulMessage>,
+    /// A copy of the audio thread's timeline, only its position is sent over
+    timeline: Timeline,
+    tape_lengths: [usize; TAPE_COUNT],
+    input_routes: [InputRoute; TAPE_COUNT],
+    record_enabled: [bool; TAPE_COUNT],
+    punch_regions: [Option<PunchRegion>; TAPE_COUNT],
+    takes: [Vec<usize>; TAPE_COUNT],
+    /// Follows the audio thread, auto-advance can change it
+    primary_tape: usize,
+    /// Follow the audio thread, recalling a scene changes them
+    tape_mixes: [TapeMix; TAPE_COUNT],
+    stored_scenes: [bool; SCENE_COUNT],
+    /// The section of the arrangement that is playing in song mode
+    section: Option<usize>,
+    automation_modes: [AutomationMode; TAPE_COUNT],
+    automation_graphs: [Option<[f32; SAMPLE_GRAPH_SIZE]>; TAPE_COUNT],
+    active_takes: [Option<usize>; TAPE_COUNT],
+    quantize: Quantize,
+    transport: TransportState,
+    action_producer: HeapProd<ScheduledAction>,
     modul_message_consumer: HeapCons<ModulMessage>,
+    worker_message_consumer: HeapCons<ModulMessage>,
     is_recording: bool,
+    is_armed: bool,
     is_recording_playback: bool,
     is_play_through: bool,
     sample_averages: [f32; TAPE_COUNT + 1],
+    latency: Latency,
+    /// Frames the input callback dropped because the output fell behind
+    dropped_input_frames: Arc<AtomicUsize>,
+    input_peak: f32,
+    is_input_gate_open: bool,
+    master_peaks: [f32; TAPE_CHANNELS],
+    /// Every tape and then the master bus
+    ducker_settings: [DuckerSettings; TAPE_COUNT + 1],
+    gain_reductions: [f32; TAPE_COUNT + 1],
+    synth_settings: SynthSettings,
     pub samples_for_graphs: [[f32; SAMPLE_GRAPH_SIZE]; TAPE_COUNT],
     _show_beat: bool,
-    beat_index: u32,
+    beat_index: u64,
     pub stats: Stats,
     pub message_history: VecDeque<String>,
-    log_consumer: HeapCons<String>,
     pub instant: std::time::Instant,
 }
 
@@ -62,7 +99,12 @@ impl Modul {
         //     sample_rate: SampleRate(48000),
         //     buffer_size: BufferSize::Fixed(512),
         // };
-        let input_config: StreamConfig = input_device.default_input_config().unwrap().into();
+        // Devices that default to a sample format modul can't convert fall back to another config
+        let input_supported_config = supported_config(
+            input_device.default_input_config().unwrap(),
+            input_device.supported_input_configs().unwrap(),
+        )
+        .expect("the input device has no supported sample format");
 
         let beats = 4.0; // This corresponds to the time, at the moment it is 4/4
         let seconds_per_beat = 60.0 / config.bpm as f32;
@@ -74,16 +116,26 @@ impl Modul {
         //     sample_rate: SampleRate(48000),
         //     buffer_size: BufferSize::Fixed(512),
         // };
-        let output_config: StreamConfig = output_device.default_output_config().unwrap().into();
-
-        /*
-        ATTENTION:
-        If buffer capacity and update frequency is related, if update frequency is low
-        then the buffer will not be emptied fast enough and some input will be lost
-        This is unnecesary since I don't push to the buffer if buffer.len() is 2048
-        */
+        let output_supported_config = supported_config(
+            output_device.default_output_config().unwrap(),
+            output_device.supported_output_configs().unwrap(),
+        )
+        .expect("the output device has no supported sample format");
+        // Tapes are recorded and played at one sample rate, the input follows the output
+        // Nothing is resampled, an input device that can't run at that rate is rejected
+        let input_supported_config = config_at_sample_rate(
+            input_supported_config,
+            input_device.supported_input_configs().unwrap(),
+            output_supported_config.sample_rate(),
+        )
+        .expect("the input device can't run at the sample rate of the output device");
+        let input_sample_format = input_supported_config.sample_format();
+        let input_config: StreamConfig = input_supported_config.into();
+        let output_sample_format = output_supported_config.sample_format();
+        let output_config: StreamConfig = output_supported_config.into();
+
         const BUFFER_SIZE: u32 = 128; // Suggested buffer size for recording is 128, in my tests even 32 works fine
-        const RING_BUFFER_CAPACITY: usize = 8192;
+        const RING_BUFFER_FRAMES: usize = 4096;
 
         let message_history = VecDeque::with_capacity(10);
         // input_config.sample_rate = cpal::SampleRate(48000);
@@ -91,6 +143,10 @@ impl Modul {
         // input_config.buffer_size = BufferSize::Fixed(BUFFER_SIZE);
         println!("input config: {:?}", input_config);
         println!("output config: {:?}", output_config);
+        println!(
+            "input sample format: {}, output sample format: {}",
+            input_sample_format, output_sample_format
+        );
 
         let output_buffer_size = match output_device
             .default_output_config()
@@ -101,104 +157,116 @@ impl Modul {
             BufferSize::Default => 512,
             BufferSize::Fixed(v) => v,
         };
-        let stats = Stats {
+        let mut stats = Stats {
             fps: 0.0,
-            bpm: config.bpm,
+            bpm: config.bpm as f32,
             bar_count: config.bar_count,
             bar_length,
             input_device_name: input_device.name().unwrap(),
             input_channel_count: input_config.channels,
+            input_sample_format,
             input_buffer_size: BUFFER_SIZE,
             output_device_name: output_device.name().unwrap(),
             output_channel_count: output_config.channels,
+            output_sample_format,
             output_buffer_size,
         };
 
-        // sample rate * channel count(4 on personal mac) * bar length in seconds * bar count
-        let mut tape_length: usize = (input_config.sample_rate.0 as f32
-            * input_config.channels as f32
-            * bar_length
-            * config.bar_count as f32) as usize;
-        tape_length -= tape_length % input_config.channels as usize;
+        // In frames, sample rate * bar length in seconds * bar count
+        let mut tape_length: usize =
+            (input_config.sample_rate.0 as f32 * bar_length * config.bar_count as f32) as usize;
+        // Whole frames for every beat, so the loop can be divided down to a quarter
+        tape_length -= tape_length % (config.bar_count * 4);
 
-        let tape_model = TapeModel::new(tape_length);
-
-        let ten_minutes_in_seconds = 10 * 60;
-        let preallocated_capacity = input_config.sample_rate.0 as usize
-            * input_config.channels as usize
-            * ten_minutes_in_seconds;
+        // The tempo is set from the loop, so beats don't drift away from the tapes
+        let mut timeline = Timeline::new(input_config.sample_rate.0, config.bpm as f64);
+        let bpm = timeline.bpm_for_length(tape_length, config.bar_count * 4);
+        timeline.set_tempo(bpm);
+        stats.bpm = timeline.bpm() as f32;
 
         println!(
-            "tape length: {}, bar length: {} seconds, writing tape: {}",
-            tape_length, bar_length, preallocated_capacity
+            "tape length: {}, bar length: {} seconds",
+            tape_length, bar_length
         );
 
-        let message_buffer = HeapRb::<String>::new(10);
-        let (mut message_producer, mut message_consumer) = message_buffer.split();
-
-        let audio_ring_buffer = HeapRb::<Input>::new(RING_BUFFER_CAPACITY);
-        let (mut input_producer, mut input_consumer) = audio_ring_buffer.split();
+        let input_channel_count = input_config.channels as usize;
+        let audio_ring_buffer = HeapRb::<f32>::new(RING_BUFFER_FRAMES * input_channel_count);
+        let (input_producer, input_consumer) = audio_ring_buffer.split();
 
-        let action_ring_buffer = HeapRb::<ModulAction>::new(16);
-        let (mut action_producer, mut action_consumer) = action_ring_buffer.split();
-
-        let message_ring_buffer_to = HeapRb::<ModulMessage>::new(2_usize.pow(10));
-        let (mut modul_message_producer, mut audio_message_consumer) =
-            message_ring_buffer_to.split();
+        let action_ring_buffer = HeapRb::<ScheduledAction>::new(ACTION_QUEUE_SIZE);
+        let (action_producer, action_consumer) = action_ring_buffer.split();
 
         let message_ring_buffer_from = HeapRb::<ModulMessage>::new(2_usize.pow(10));
-        let (mut audio_message_producer, mut modul_message_consumer) =
-            message_ring_buffer_from.split();
+        let (audio_message_producer, modul_message_consumer) = message_ring_buffer_from.split();
 
-        let output_ring_buffer = HeapRb::<f32>::new(RING_BUFFER_CAPACITY);
-        let (mut output_producer, mut output_consumer) = output_ring_buffer.split();
+        let worker_message_ring_buffer = HeapRb::<ModulMessage>::new(2_usize.pow(6));
+        let (worker_message_producer, worker_message_consumer) = worker_message_ring_buffer.split();
 
-        let input_stream =
-            create_input_stream_live(&input_device, &input_config, tape_length, input_producer);
+        let job_ring_buffer = HeapRb::<Job>::new(JOB_QUEUE_SIZE);
+        let (job_producer, job_consumer) = job_ring_buffer.split();
 
-        let audio_index = 0;
+        let done_ring_buffer = HeapRb::<Done>::new(2_usize.pow(6));
+        let (done_producer, done_consumer) = done_ring_buffer.split();
 
-        let output_stream =
-            create_output_stream_live(&output_device, &output_config, output_consumer);
+        // The synth is played into two more channels after the channels of the device
+        let channel_count = input_channel_count + TAPE_CHANNELS;
+        let capture_buffer = CaptureBuffer::new(channel_count, tape_length * CAPTURE_LOOP_COUNT);
+
+        let worker = Worker::new(
+            tape_length,
+            output_config.sample_rate.0,
+            capture_buffer.clone(),
+            job_consumer,
+            done_producer,
+            worker_message_producer,
+        );
+        std::thread::spawn(move || worker.run());
+
+        let dropped_input_frames = Arc::new(AtomicUsize::new(0));
+        let input_stream = create_input_stream_live(
+            &input_device,
+            &input_config,
+            input_sample_format,
+            input_producer,
+            dropped_input_frames.clone(),
+        )
+        .unwrap();
 
         let sample_averages = [0.0; TAPE_COUNT + 1];
         let samples_for_graphs = [[0.0; SAMPLE_GRAPH_SIZE]; TAPE_COUNT];
         let show_beat = false;
         let beat_index = 0;
 
-        let mut audio_model: AudioModel = AudioModel {
-            tape_length,
-            recording_tape: Vec::with_capacity(preallocated_capacity),
-            tape_model,
+        let format = StreamFormat {
+            input_sample_rate: input_config.sample_rate.0,
+            output_sample_rate: output_config.sample_rate.0,
+            input_channel_count,
+            output_channel_count: output_config.channels as usize,
+        };
+        let queues = AudioQueues {
             input_consumer,
             action_consumer,
             audio_message_producer,
-            audio_message_consumer,
-            is_recording: false,
-            is_recording_playback: false,
-            is_play_through: false,
-            audio_index,
-            primary_tape: 0,
-            secondary_tapes: [false; TAPE_COUNT],
-            output_producer,
-            writing_tape: Vec::with_capacity(preallocated_capacity),
-            sample_averages,
-            samples_for_graphs,
-            show_beat,
-            beat_index,
-            metronome: Metronome::new(
-                config.bpm,
-                input_config.sample_rate.0,
-                input_config.channels as u32,
-            ),
-            output_channel_count: output_config.channels as usize,
-            log_producer: message_producer,
+            job_producer,
+            done_consumer,
         };
+        let mut audio_model = AudioModel::new(
+            config,
+            timeline.clone(),
+            tape_length,
+            format,
+            capture_buffer,
+            queues,
+        );
 
-        std::thread::spawn(move || loop {
-            audio_model.update();
-            std::thread::sleep(Duration::from_micros(1000));
-        });
+        // In debug builds anything that allocates while mixing aborts the program
+        let output_stream = create_output_stream_live(
+            &output_device,
+            &output_config,
+            output_sample_format,
+            move |output: &mut [f32]| assert_no_alloc(|| audio_model.process(output)),
+        )
+        .unwrap();
 
         input_stream.play().unwrap();
         output_stream.play().unwrap();
@@ -208,52 +276,102 @@ impl Modul {
             _input_stream: input_stream,
             _output_stream: output_stream,
             _time: 0.0,
-            audio_index,
+            timeline,
+            tape_lengths: [tape_length; TAPE_COUNT],
+            input_routes: [InputRoute::default(); TAPE_COUNT],
+            record_enabled: [false; TAPE_COUNT],
+            punch_regions: [None; TAPE_COUNT],
+            takes: std::array::from_fn(|_| Vec::new()),
+            primary_tape: 0,
+            tape_mixes: [TapeMix::default(); TAPE_COUNT],
+            stored_scenes: [false; SCENE_COUNT],
+            section: None,
+            automation_modes: [AutomationMode::Off; TAPE_COUNT],
+            automation_graphs: [None; TAPE_COUNT],
+            active_takes: [None; TAPE_COUNT],
+            quantize: Quantize::Off,
+            transport: TransportState::Playing,
             is_recording: false,
+            is_armed: false,
             is_recording_playback: false,
             is_play_through: false,
             action_producer,
-            _modul_message_producer: modul_message_producer,
             modul_message_consumer,
             sample_averages,
+            latency: Latency::default(),
+            dropped_input_frames,
+            input_peak: 0.0,
+            is_input_gate_open: true,
+            ducker_settings: [DuckerSettings::default(); TAPE_COUNT + 1],
+            gain_reductions: [0.0; TAPE_COUNT + 1],
+            synth_settings: SynthSettings::default(),
+            master_peaks: [0.0; TAPE_CHANNELS],
             samples_for_graphs,
             _show_beat: show_beat,
             beat_index,
             stats,
             message_history,
-            log_consumer: message_consumer,
+            worker_message_consumer,
             instant: std::time::Instant::now(),
         }
     }
 
     pub fn update(&mut self) {
-        while !self.modul_message_consumer.is_empty() {
-            let message = self.modul_message_consumer.try_pop().unwrap();
-            match message {
-                ModulMessage::AudioIndex(audio_index) => self.audio_index = audio_index,
-                ModulMessage::Recording(is_recording) => self.is_recording = is_recording,
-                ModulMessage::RecordingPlayback(is_recording_playback) => {
-                    self.is_recording_playback = is_recording_playback
-                }
-                ModulMessage::PlayThrough(is_play_through) => {
-                    self.is_play_through = is_play_through
-                }
-                ModulMessage::ShowBeat(show_beat) => self._show_beat = show_beat,
-                ModulMessage::BeatIndex(beat_index) => self.beat_index = beat_index,
-                ModulMessage::SampleAverages(sample_averages) => {
-                    self.sample_averages = sample_averages
-                }
-                ModulMessage::SamplesForGraphs(samples_for_graphs) => {
-                    self.samples_for_graphs = samples_for_graphs
-                }
-            }
+        while let Some(message) = self.modul_message_consumer.try_pop() {
+            self.handle_message(message);
+        }
+        while let Some(message) = self.worker_message_consumer.try_pop() {
+            self.handle_message(message);
         }
-        while !self.log_consumer.is_empty() {
-            let message = self.log_consumer.try_pop().unwrap();
-            self.add_message(message);
+    }
+
+    fn handle_message(&mut self, message: ModulMessage) {
+        match message {
+            ModulMessage::Position(position) => self.timeline.locate(position),
+            ModulMessage::Transport(transport) => self.transport = transport,
+            ModulMessage::TapeLengths(tape_lengths) => self.tape_lengths = tape_lengths,
+            ModulMessage::Recording(is_recording) => self.is_recording = is_recording,
+            ModulMessage::Armed(is_armed) => self.is_armed = is_armed,
+            ModulMessage::RecordingPlayback(is_recording_playback) => {
+                self.is_recording_playback = is_recording_playback
+            }
+            ModulMessage::PlayThrough(is_play_through) => self.is_play_through = is_play_through,
+            ModulMessage::ShowBeat(show_beat) => self._show_beat = show_beat,
+            ModulMessage::BeatIndex(beat_index) => self.beat_index = beat_index,
+            ModulMessage::SampleAverages(sample_averages) => self.sample_averages = sample_averages,
+            ModulMessage::SamplesForGraphs(samples_for_graphs) => {
+                self.samples_for_graphs = samples_for_graphs
+            }
+            ModulMessage::Latency(latency) => self.latency = latency,
+            ModulMessage::LoopDefined { tape_length, bpm } => {
+                self.tape_length = tape_length;
+                self.timeline.set_tempo(bpm);
+                self.stats.bpm = self.timeline.bpm() as f32;
+                self.stats.bar_length = (4.0 * 60.0 / bpm) as f32;
+            }
+            ModulMessage::InputLevel { peak, is_gate_open } => {
+                self.input_peak = peak;
+                self.is_input_gate_open = is_gate_open;
+            }
+            ModulMessage::MasterLevel(master_peaks) => self.master_peaks = master_peaks,
+            ModulMessage::GainReductions(gain_reductions) => self.gain_reductions = gain_reductions,
+            ModulMessage::PrimaryTape(primary_tape) => self.primary_tape = primary_tape,
+            ModulMessage::TapeMixes(tape_mixes) => self.tape_mixes = tape_mixes,
+            ModulMessage::Section(section) => self.section = section,
+            ModulMessage::AutomationGraph { id, points } => self.automation_graphs[id] = points,
+            ModulMessage::Takes {
+                id,
+                numbers,
+                active,
+            } => {
+                self.takes[id] = numbers;
+                self.active_takes[id] = active;
+            }
+            ModulMessage::Log(message) => self.add_message(message.to_string()),
         }
     }
 
+    #[allow(dead_code)]
     pub fn resize(&self) {
         dbg!("testing resize");
     }
@@ -270,14 +388,54 @@ impl Modul {
         self._time
     }
 
-    pub fn get_audio_index(&self) -> usize {
-        self.audio_index
+    /// Where the tape is playing, from 0 to 1, tapes can be longer or shorter than the loop
+    pub fn get_tape_position(&self, id: usize) -> f32 {
+        let length = self.tape_lengths[id] as u64;
+        (self.timeline.position() % length) as f32 / length as f32
+    }
+
+    /// Length of the tape in loops
+    pub fn get_tape_ratio(&self, id: usize) -> f32 {
+        self.tape_lengths[id] as f32 / self.tape_length as f32
+    }
+
+    pub fn get_musical_position(&self) -> MusicalPosition {
+        self.timeline.musical(self.timeline.position())
+    }
+
+    pub fn get_seconds(&self) -> f64 {
+        self.timeline.seconds(self.timeline.position())
+    }
+
+    pub fn get_transport(&self) -> TransportState {
+        self.transport
+    }
+
+    pub fn play(&mut self) {
+        self.push_action(ModulAction::Play);
+    }
+
+    pub fn pause(&mut self) {
+        self.push_action(ModulAction::Pause);
+    }
+
+    pub fn stop(&mut self) {
+        self.push_action(ModulAction::Stop);
+    }
+
+    /// `bar` is counted from 0
+    pub fn locate(&mut self, bar: usize) {
+        self.push_action(ModulAction::Locate(bar));
     }
 
     pub fn is_recording(&self) -> bool {
         self.is_recording
     }
 
+    pub fn is_armed(&self) -> bool {
+        self.is_armed
+    }
+
     pub fn is_recording_playback(&self) -> bool {
         self.is_recording_playback
     }
@@ -286,99 +444,323 @@ impl Modul {
         self.is_play_through
     }
 
+    /// Actions from the UI are applied at the start of the next output block
+    fn push_action(&mut self, action: ModulAction) {
+        self.schedule(action, Timestamp::Now);
+    }
+
+    /// The action is applied on the exact sample of `timestamp`
+    /// If the audio thread can't keep up the action is dropped here, it must not be freed there
+    pub fn schedule(&mut self, action: ModulAction, timestamp: Timestamp) {
+        if self
+            .action_producer
+            .try_push(ScheduledAction { action, timestamp })
+            .is_err()
+        {
+            self.add_message(LogMessage::TooManyScheduledActions.to_string());
+        }
+    }
+
+    pub fn set_quantize(&mut self, quantize: Quantize) {
+        self.quantize = quantize;
+    }
+
+    fn quantized_timestamp(&self) -> Timestamp {
+        match self.quantize {
+            Quantize::Off => Timestamp::Now,
+            Quantize::Beat => Timestamp::NextBeat,
+            Quantize::Bar => Timestamp::NextBar,
+        }
+    }
+
+    pub fn get_primary_tape(&self) -> usize {
+        self.primary_tape
+    }
+
     pub fn select_primary_tape(&mut self, primary_tape: usize) {
-        self.action_producer
-            .try_push(ModulAction::SelectPrimaryTape(primary_tape))
-            .unwrap();
+        self.primary_tape = primary_tape;
+        self.push_action(ModulAction::SelectPrimaryTape(primary_tape));
     }
 
     pub fn select_secondary_tape(&mut self, secondary_tape: usize) {
-        self.action_producer
-            .try_push(ModulAction::SelectSecondaryTape(secondary_tape))
-            .unwrap();
+        self.push_action(ModulAction::SelectSecondaryTape(secondary_tape));
     }
 
     pub fn merge_tapes(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::MergeTapes)
-            .unwrap();
+        self.push_action(ModulAction::MergeTapes);
     }
 
     pub fn _show_beat(&self) -> bool {
         self._show_beat
     }
 
-    pub fn get_beat_index(&self) -> u32 {
+    pub fn get_beat_index(&self) -> u64 {
         self.beat_index
     }
 
     pub fn switch_metronome(&mut self, is_active: bool) {
         if is_active {
-            self.action_producer
-                .try_push(ModulAction::StartMetronome)
-                .unwrap();
+            self.push_action(ModulAction::StartMetronome);
         } else {
-            self.action_producer
-                .try_push(ModulAction::StopMetronome)
-                .unwrap();
+            self.push_action(ModulAction::StopMetronome);
         }
     }
 
     pub fn record(&mut self) {
-        self.action_producer.try_push(ModulAction::Record).unwrap();
+        self.schedule(ModulAction::Record, self.quantized_timestamp());
+    }
+
+    pub fn arm_record(&mut self) {
+        self.push_action(ModulAction::ArmRecord);
+    }
+
+    pub fn get_input_route(&self, id: usize) -> InputRoute {
+        self.input_routes[id]
+    }
+
+    pub fn set_input_route(&mut self, id: usize, route: InputRoute) {
+        self.input_routes[id] = route;
+        self.push_action(ModulAction::SetInputRoute { id, route });
+    }
+
+    pub fn is_record_enabled(&self, id: usize) -> bool {
+        self.record_enabled[id]
+    }
+
+    /// The tape is recorded together with the primary tape, with its own input route
+    pub fn toggle_record_enabled(&mut self, id: usize) {
+        self.record_enabled[id] = !self.record_enabled[id];
+        self.push_action(ModulAction::ToggleRecordEnabled(id));
+    }
+
+    pub fn get_punch_region(&self, id: usize) -> Option<PunchRegion> {
+        self.punch_regions[id]
+    }
+
+    pub fn set_punch_region(&mut self, id: usize, region: Option<PunchRegion>) {
+        self.punch_regions[id] = region;
+        self.push_action(ModulAction::SetPunchRegion { id, region });
+    }
+
+    /// Where the punch region starts and ends, from 0 to 1 of the tape
+    pub fn get_punch_ratio(&self, id: usize) -> Option<(f32, f32)> {
+        let length = self.tape_lengths[id] as f32;
+        self.punch_regions[id].map(|region| {
+            (
+                self.timeline.position_of_beats(region.start) as f32 / length,
+                self.timeline.position_of_beats(region.end) as f32 / length,
+            )
+        })
+    }
+
+    /// Numbers of the takes that are kept for the tape
+    pub fn get_takes(&self, id: usize) -> &[usize] {
+        &self.takes[id]
+    }
+
+    pub fn get_active_take(&self, id: usize) -> Option<usize> {
+        self.active_takes[id]
+    }
+
+    pub fn select_take(&mut self, id: usize, number: usize) {
+        self.push_action(ModulAction::SelectTake { id, number });
+    }
+
+    /// `comp` has the take of every bar of the tape
+    pub fn flatten_comp(&mut self, id: usize, comp: Vec<Option<usize>>) {
+        self.push_action(ModulAction::FlattenComp { id, comp });
+    }
+
+    /// Bars in the tape, multiplied tapes have more bars than the loop
+    pub fn get_bar_count(&self, id: usize) -> usize {
+        self.tape_lengths[id] * self.stats.bar_count / self.tape_length
+    }
+
+    pub fn set_record_settings(&mut self, record_settings: RecordSettings) {
+        self.push_action(ModulAction::SetRecordSettings(record_settings));
+    }
+
+    pub fn capture(&mut self, bar_count: usize) {
+        self.push_action(ModulAction::Capture(bar_count));
+    }
+
+    pub fn multiply_tape(&mut self, factor: usize) {
+        self.push_action(ModulAction::MultiplyTape(factor));
+    }
+
+    pub fn divide_tape(&mut self, divisor: usize) {
+        self.push_action(ModulAction::DivideTape(divisor));
     }
 
     pub fn record_playback(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::RecordPlayback)
-            .unwrap();
+        self.push_action(ModulAction::RecordPlayback);
     }
 
     pub fn play_through(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::PlayThrough)
-            .unwrap();
+        self.push_action(ModulAction::PlayThrough);
     }
 
     pub fn write(&mut self) {
-        self.action_producer.try_push(ModulAction::Write).unwrap();
+        self.push_action(ModulAction::Write);
     }
 
     pub fn clear_all(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::ClearAll)
-            .unwrap();
+        self.push_action(ModulAction::ClearAll);
     }
 
     pub fn clear(&mut self) {
-        self.action_producer.try_push(ModulAction::Clear).unwrap();
+        self.push_action(ModulAction::Clear);
     }
 
     pub fn toggle_mute(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::ToggleMute)
-            .unwrap();
+        self.schedule(ModulAction::ToggleMute, self.quantized_timestamp());
     }
 
     pub fn toggle_solo(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::ToggleSolo)
-            .unwrap();
+        self.schedule(ModulAction::ToggleSolo, self.quantized_timestamp());
     }
 
     pub fn volume_up(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::VolumeUp)
-            .unwrap();
+        self.push_action(ModulAction::VolumeUp);
     }
 
     pub fn volume_down(&mut self) {
-        self.action_producer
-            .try_push(ModulAction::VolumeDown)
-            .unwrap();
+        self.push_action(ModulAction::VolumeDown);
+    }
+
+    pub fn pan_left(&mut self) {
+        self.push_action(ModulAction::PanLeft);
+    }
+
+    pub fn pan_right(&mut self) {
+        self.push_action(ModulAction::PanRight);
+    }
+
+    pub fn get_tape_mix(&self, id: usize) -> TapeMix {
+        self.tape_mixes[id]
+    }
+
+    pub fn is_scene_stored(&self, index: usize) -> bool {
+        self.stored_scenes[index]
+    }
+
+    pub fn store_scene(&mut self, index: usize) {
+        self.stored_scenes[index] = true;
+        self.push_action(ModulAction::StoreScene(index));
+    }
+
+    /// A quantized scene changes on the next bar
+    pub fn recall_scene(&mut self, index: usize, fade_time: f32, is_quantized: bool) {
+        let timestamp = if is_quantized {
+            Timestamp::NextBar
+        } else {
+            Timestamp::Now
+        };
+        self.schedule(ModulAction::RecallScene { index, fade_time }, timestamp);
+    }
+
+    pub fn get_automation_mode(&self, id: usize) -> AutomationMode {
+        self.automation_modes[id]
+    }
+
+    pub fn set_automation_mode(&mut self, id: usize, mode: AutomationMode) {
+        self.automation_modes[id] = mode;
+        self.push_action(ModulAction::SetAutomationMode { id, mode });
+    }
+
+    /// Volume over one loop, from 0 to 1
+    pub fn get_automation_graph(&self, id: usize) -> Option<&[f32; SAMPLE_GRAPH_SIZE]> {
+        self.automation_graphs[id].as_ref()
+    }
+
+    pub fn clear_automation(&mut self, id: usize) {
+        self.push_action(ModulAction::ClearAutomation(id));
+    }
+
+    pub fn set_modulation(&mut self, modulation: ModulationSettings) {
+        self.push_action(ModulAction::SetModulation(Box::new(modulation)));
+    }
+
+    pub fn set_arrangement(&mut self, arrangement: Arrangement) {
+        self.push_action(ModulAction::SetArrangement(Box::new(arrangement)));
+    }
+
+    /// Turning song mode on plays the arrangement from the start
+    pub fn set_song_mode(&mut self, is_song_mode: bool) {
+        self.push_action(ModulAction::SetSongMode(is_song_mode));
+    }
+
+    pub fn get_section(&self) -> Option<usize> {
+        self.section
+    }
+
+    /// Written to out/song.wav, stems to out/song_tape_<n>.wav
+    pub fn render_arrangement(&mut self, with_stems: bool) {
+        self.push_action(ModulAction::RenderArrangement { with_stems });
     }
 
     pub fn get_sample_averages(&self) -> [f32; TAPE_COUNT + 1] {
         self.sample_averages
     }
+
+    pub fn get_latency(&self) -> Latency {
+        self.latency
+    }
+
+    pub fn get_dropped_input_frames(&self) -> usize {
+        self.dropped_input_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn get_input_peak(&self) -> f32 {
+        self.input_peak
+    }
+
+    pub fn get_master_peaks(&self) -> [f32; TAPE_CHANNELS] {
+        self.master_peaks
+    }
+
+    /// `target` is a tape, TAPE_COUNT is the master bus
+    pub fn get_ducker(&self, target: usize) -> DuckerSettings {
+        self.ducker_settings[target]
+    }
+
+    pub fn set_ducker(&mut self, target: usize, settings: DuckerSettings) {
+        self.ducker_settings[target] = settings;
+        self.push_action(ModulAction::SetDucker { target, settings });
+    }
+
+    /// In dB
+    pub fn get_gain_reduction(&self, target: usize) -> f32 {
+        self.gain_reductions[target]
+    }
+
+    pub fn get_synth(&self) -> SynthSettings {
+        self.synth_settings
+    }
+
+    pub fn set_synth(&mut self, settings: SynthSettings) {
+        self.synth_settings = settings;
+        self.push_action(ModulAction::SetSynth(settings));
+    }
+
+    /// MIDI note numbers, `velocity` is from 0 to 1
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        self.push_action(ModulAction::NoteOn { note, velocity });
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        self.push_action(ModulAction::NoteOff(note));
+    }
+
+    pub fn set_output_settings(&mut self, settings: OutputSettings) {
+        self.push_action(ModulAction::SetOutputSettings(settings));
+    }
+
+    pub fn is_input_gate_open(&self) -> bool {
+        self.is_input_gate_open
+    }
+
+    pub fn set_input_settings(&mut self, settings: InputSettings) {
+        self.push_action(ModulAction::SetInputSettings(settings));
+    }
 }
@@ -0,0 +1,206 @@
+use crate::core::*;
+use crate::features::{
+    Ducker, DuckerSettings, LowPass, ModulationSettings, Tape, TapeMix, TapeModulation,
+    FOLLOWER_RELEASE_TIME,
+};
+
+/// Modulates and ducks every tape, mixes them into a bus and ducks the bus
+/// The output callback plays through it and the Worker renders the song through it,
+/// so a rendered song sounds like the song played back
+/// Nothing in here allocates once it is created
+pub struct TapeMixer {
+    sample_rate: u32,
+    /// Swapped with the settings that come from the UI, the old ones are freed by the Worker
+    pub modulation: Box<ModulationSettings>,
+    /// Envelope followers of every tape and then the input
+    envelopes: [f32; TAPE_COUNT + 1],
+    tape_modulations: [TapeModulation; TAPE_COUNT],
+    /// In frames, where the playback rate has moved every tape at the end of the last segment
+    rate_offsets: [f64; TAPE_COUNT],
+    low_passes: [LowPass; TAPE_COUNT],
+    /// Tapes that are modulated or ducked are read into these before they are mixed
+    tape_buses: [TapeAudio; TAPE_COUNT],
+    /// One for every tape and then the bus
+    duckers: [Ducker; TAPE_COUNT + 1],
+    /// The largest reduction of every ducker since it was last reset, in dB
+    pub gain_reductions: [f32; TAPE_COUNT + 1],
+}
+
+impl TapeMixer {
+    pub fn new(sample_rate: u32, modulation: Box<ModulationSettings>) -> Self {
+        Self {
+            sample_rate,
+            modulation,
+            envelopes: [0.0; TAPE_COUNT + 1],
+            tape_modulations: [TapeModulation::default(); TAPE_COUNT],
+            rate_offsets: [0.0; TAPE_COUNT],
+            low_passes: [LowPass::default(); TAPE_COUNT],
+            tape_buses: std::array::from_fn(|_| {
+                std::array::from_fn(|_| vec![0.0; MAX_BLOCK_FRAMES])
+            }),
+            duckers: [Ducker::default(); TAPE_COUNT + 1],
+            gain_reductions: [0.0; TAPE_COUNT + 1],
+        }
+    }
+
+    /// `target` is a tape or TAPE_COUNT for the bus
+    pub fn set_ducker(&mut self, target: usize, settings: DuckerSettings) {
+        self.duckers[target].set_settings(settings, self.sample_rate);
+    }
+
+    pub fn ducker_settings(&self) -> [DuckerSettings; TAPE_COUNT + 1] {
+        std::array::from_fn(|target| self.duckers[target].settings())
+    }
+
+    /// Modulation is updated between segments, they are kept short while there are routes
+    pub fn is_modulated(&self) -> bool {
+        !self.modulation.routes().is_empty()
+    }
+
+    /// Follows the levels of the segment that is about to play, then sums the routes
+    /// `input_peak` is the loudest input sample in the segment, `beats` is where it starts
+    pub fn modulate(
+        &mut self,
+        tapes: &[Tape<f32>; TAPE_COUNT],
+        indices: [usize; TAPE_COUNT],
+        len: usize,
+        input_peak: f32,
+        beats: f64,
+    ) {
+        if !self.is_modulated() {
+            self.tape_modulations = [TapeModulation::default(); TAPE_COUNT];
+            return;
+        }
+        let release = (-(len as f32) / (FOLLOWER_RELEASE_TIME * self.sample_rate as f32)).exp();
+        for (id, tape) in tapes.iter().enumerate() {
+            let index = indices[id];
+            let peak = tape
+                .audio
+                .iter()
+                .flat_map(|channel| &channel[index..index + len])
+                .fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+            self.envelopes[id] = peak.max(self.envelopes[id] * release);
+        }
+        self.envelopes[TAPE_COUNT] = input_peak.max(self.envelopes[TAPE_COUNT] * release);
+
+        self.tape_modulations = self.modulation.modulate(beats, &self.envelopes);
+    }
+
+    /// Volume and pan of every tape moved by the modulation of the segment
+    pub fn apply(&self, mut mixes: [TapeMix; TAPE_COUNT]) -> [TapeMix; TAPE_COUNT] {
+        for (mix, modulation) in mixes.iter_mut().zip(&self.tape_modulations) {
+            *mix = modulation.apply(*mix);
+        }
+
+        mixes
+    }
+
+    /// Mixes every tape, starting at `indices`, into `bus` and ducks it
+    /// The loudest sample of every tape is kept in `peaks`
+    pub fn mix(
+        &mut self,
+        tapes: &[Tape<f32>; TAPE_COUNT],
+        indices: [usize; TAPE_COUNT],
+        gains: &[[f32; TAPE_COUNT]; TAPE_CHANNELS],
+        bus: [&mut [f32]; TAPE_CHANNELS],
+        peaks: &mut [f32],
+    ) {
+        let len = bus[0].len();
+        let is_processed: [bool; TAPE_COUNT] =
+            std::array::from_fn(|id| self.process_tape(tapes, id, indices, len));
+        let [left, right] = bus;
+        for ((channel, bus), gains) in [&mut *left, &mut *right].into_iter().enumerate().zip(gains)
+        {
+            let audio: [&[f32]; TAPE_COUNT] = std::array::from_fn(|id| {
+                if is_processed[id] {
+                    &self.tape_buses[id][channel][..len]
+                } else {
+                    &tapes[id].audio[channel][indices[id]..indices[id] + len]
+                }
+            });
+            mixer::mix_tapes(bus, &audio, gains, peaks);
+        }
+
+        // The bus is ducked after the tapes are mixed into it
+        if let Some(key) = self.duckers[TAPE_COUNT].key() {
+            let key_audio = key_audio(&tapes[key], indices[key], len);
+            let reduction = self.duckers[TAPE_COUNT].process(key_audio, [left, right]);
+            self.gain_reductions[TAPE_COUNT] = self.gain_reductions[TAPE_COUNT].max(reduction);
+        }
+    }
+
+    /// Tapes that are modulated or ducked are read into their own bus first
+    /// Returns false if the tape can be mixed as it is
+    fn process_tape(
+        &mut self,
+        tapes: &[Tape<f32>; TAPE_COUNT],
+        id: usize,
+        indices: [usize; TAPE_COUNT],
+        len: usize,
+    ) -> bool {
+        let index = indices[id];
+        let is_modulated = self.modulate_tape(&tapes[id], id, index, len);
+        let Some(key) = self.duckers[id].key() else {
+            return is_modulated;
+        };
+        let bus = &mut self.tape_buses[id];
+        if !is_modulated {
+            for (bus, audio) in bus.iter_mut().zip(&tapes[id].audio) {
+                bus[..len].copy_from_slice(&audio[index..index + len]);
+            }
+        }
+        let key_audio = key_audio(&tapes[key], indices[key], len);
+        let [left, right] = bus;
+        let reduction = self.duckers[id].process(key_audio, [&mut left[..len], &mut right[..len]]);
+        self.gain_reductions[id] = self.gain_reductions[id].max(reduction);
+
+        true
+    }
+
+    /// Tapes with a moving playback rate or a closed filter are read into their own bus
+    /// Returns false if the tape is neither
+    fn modulate_tape(&mut self, tape: &Tape<f32>, id: usize, index: usize, len: usize) -> bool {
+        let modulation = self.tape_modulations[id];
+        let length = tape.length();
+        let previous = self.rate_offsets[id];
+        let rate_offset = modulation
+            .rate_offset(self.sample_rate)
+            .clamp(-(length as f64), length as f64);
+        self.rate_offsets[id] = rate_offset;
+        let cutoff = modulation.cutoff_frequency();
+        if rate_offset == 0.0 && previous == 0.0 && cutoff.is_none() {
+            self.low_passes[id].reset();
+            return false;
+        }
+
+        for (bus, audio) in self.tape_buses[id].iter_mut().zip(&tape.audio) {
+            for (i, sample) in bus[..len].iter_mut().enumerate() {
+                // The offset moves across the segment, that is what changes the playback rate
+                let offset = previous + (rate_offset - previous) * i as f64 / len as f64;
+                let position = (index + i + length) as f64 - offset;
+                let frame = position.floor();
+                let fraction = (position - frame) as f32;
+                let frame = frame as usize % length;
+                let next = (frame + 1) % length;
+                *sample = audio[frame] + (audio[next] - audio[frame]) * fraction;
+            }
+        }
+        match cutoff {
+            Some(frequency) => {
+                let low_pass = &mut self.low_passes[id];
+                low_pass.set_cutoff(frequency, self.sample_rate);
+                for (channel, bus) in self.tape_buses[id].iter_mut().enumerate() {
+                    low_pass.process(channel, &mut bus[..len]);
+                }
+            }
+            None => self.low_passes[id].reset(),
+        }
+
+        true
+    }
+}
+
+/// Duckers listen to the key tape before its volume
+fn key_audio(tape: &Tape<f32>, index: usize, len: usize) -> [&[f32]; TAPE_CHANNELS] {
+    std::array::from_fn(|channel| &tape.audio[channel][index..index + len])
+}
@@ -0,0 +1,250 @@
+use super::utils::{MusicalPosition, TICKS_PER_BEAT};
+
+pub const MAX_TEMPO_CHANGES: usize = 64;
+pub const BEATS_PER_BAR: u64 = 4; // This corresponds to the time, at the moment it is 4/4
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    /// In frames
+    pub position: u64,
+    pub bpm: f64,
+}
+
+/// Tempo changes ordered by position, the first one is always at 0
+/// Loops keep one tempo for now, a new loop replaces the whole map
+/// Preallocated, so the tempo can be changed on the audio thread
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    pub fn new(bpm: f64) -> Self {
+        let mut changes = Vec::with_capacity(MAX_TEMPO_CHANGES);
+        changes.push(TempoChange { position: 0, bpm });
+        Self { changes }
+    }
+
+    /// One tempo for the whole timeline
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.changes.clear();
+        self.changes.push(TempoChange { position: 0, bpm });
+    }
+
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    pub fn bpm_at(&self, position: u64) -> f64 {
+        let index = self
+            .changes
+            .partition_point(|change| change.position <= position);
+        self.changes[index.max(1) - 1].bpm
+    }
+}
+
+/// The one clock everything musical reads from
+/// Positions are frames since the first bar, 64-bit so they never wrap
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    position: u64,
+    sample_rate: u64,
+    tempo_map: TempoMap,
+}
+
+impl Timeline {
+    pub fn new(sample_rate: u32, bpm: f64) -> Self {
+        Self {
+            position: 0,
+            sample_rate: sample_rate as u64,
+            tempo_map: TempoMap::new(bpm),
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn advance(&mut self, sample_count: usize) {
+        self.position += sample_count as u64;
+    }
+
+    pub fn locate(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.tempo_map.set_tempo(bpm);
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.tempo_map.bpm_at(self.position)
+    }
+
+    /// The tempo that fits `beat_count` beats into `length` frames
+    pub fn bpm_for_length(&self, length: usize, beat_count: usize) -> f64 {
+        60.0 * beat_count as f64 * self.sample_rate as f64 / length as f64
+    }
+
+    fn frames_per_beat(&self, bpm: f64) -> f64 {
+        self.sample_rate as f64 * 60.0 / bpm
+    }
+
+    pub fn seconds(&self, position: u64) -> f64 {
+        position as f64 / self.sample_rate as f64
+    }
+
+    pub fn position_of_seconds(&self, seconds: f64) -> u64 {
+        (seconds * self.sample_rate as f64).round() as u64
+    }
+
+    /// Beats since the first bar, the fraction is the position inside the beat
+    pub fn beats(&self, position: u64) -> f64 {
+        let changes = self.tempo_map.changes();
+        let mut beats = 0.0;
+        for (i, change) in changes.iter().enumerate() {
+            if change.position >= position {
+                break;
+            }
+            let end = changes
+                .get(i + 1)
+                .map_or(position, |next| next.position.min(position));
+            beats += (end - change.position) as f64 / self.frames_per_beat(change.bpm);
+        }
+        beats
+    }
+
+    pub fn position_of_beats(&self, beats: f64) -> u64 {
+        let changes = self.tempo_map.changes();
+        let mut remaining = beats;
+        for (i, change) in changes.iter().enumerate() {
+            let frames_per_beat = self.frames_per_beat(change.bpm);
+            if let Some(next) = changes.get(i + 1) {
+                let segment_beats = (next.position - change.position) as f64 / frames_per_beat;
+                if remaining >= segment_beats {
+                    remaining -= segment_beats;
+                    continue;
+                }
+            }
+            let position = change.position as f64 + remaining * frames_per_beat;
+            return position.round() as u64;
+        }
+        0
+    }
+
+    pub fn musical(&self, position: u64) -> MusicalPosition {
+        // A sample or two of rounding must not show the end of the previous beat
+        let ticks = (self.beats(position) * TICKS_PER_BEAT as f64 + 1e-6) as u64;
+        let beats = ticks / TICKS_PER_BEAT as u64;
+        MusicalPosition {
+            bar: (beats / BEATS_PER_BAR) as usize + 1,
+            beat: (beats % BEATS_PER_BAR) as usize + 1,
+            tick: (ticks % TICKS_PER_BEAT as u64) as usize,
+        }
+    }
+
+    pub fn position_of_musical(&self, musical: MusicalPosition) -> u64 {
+        let beats = (musical.bar as u64 - 1) * BEATS_PER_BAR + musical.beat as u64 - 1;
+        self.position_of_beats(beats as f64 + musical.tick as f64 / TICKS_PER_BEAT as f64)
+    }
+
+    /// `bar` is counted from 0
+    pub fn bar_position(&self, bar: usize) -> u64 {
+        self.position_of_musical(MusicalPosition {
+            bar: bar + 1,
+            beat: 1,
+            tick: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 120 BPM for the first bar, then 60 BPM
+    fn timeline_with_tempo_change() -> Timeline {
+        let mut timeline = Timeline::new(SAMPLE_RATE, 120.0);
+        timeline.tempo_map.changes.push(TempoChange {
+            position: 96000,
+            bpm: 60.0,
+        });
+        timeline
+    }
+
+    fn musical(bar: usize, beat: usize, tick: usize) -> MusicalPosition {
+        MusicalPosition { bar, beat, tick }
+    }
+
+    #[test]
+    fn beats_follow_one_tempo() {
+        let timeline = Timeline::new(SAMPLE_RATE, 120.0);
+        assert_eq!(timeline.beats(24000), 1.0);
+        assert_eq!(timeline.position_of_beats(4.0), 96000);
+        assert_eq!(timeline.bar_position(1), 96000);
+        assert_eq!(timeline.musical(36000), musical(1, 2, 480));
+    }
+
+    #[test]
+    fn beats_follow_a_tempo_change() {
+        let timeline = timeline_with_tempo_change();
+        assert_eq!(timeline.tempo_map.bpm_at(95999), 120.0);
+        assert_eq!(timeline.tempo_map.bpm_at(96000), 60.0);
+        assert_eq!(timeline.beats(72000), 3.0);
+        assert_eq!(timeline.beats(96000), 4.0);
+        assert_eq!(timeline.beats(120000), 4.5);
+        assert_eq!(timeline.position_of_beats(3.0), 72000);
+        assert_eq!(timeline.position_of_beats(5.0), 144000);
+        // The second bar is twice as long as the first one
+        assert_eq!(timeline.bar_position(1), 96000);
+        assert_eq!(timeline.bar_position(2), 288000);
+        for position in [0, 1, 47999, 95999, 96000, 96001, 200_000, 1_000_000] {
+            assert_eq!(
+                timeline.position_of_beats(timeline.beats(position)),
+                position
+            );
+        }
+    }
+
+    #[test]
+    fn bars_start_on_their_first_frame() {
+        for timeline in [
+            Timeline::new(SAMPLE_RATE, 120.0),
+            timeline_with_tempo_change(),
+        ] {
+            for bar in 1..4 {
+                let position = timeline.bar_position(bar);
+                assert_eq!(timeline.musical(position), musical(bar + 1, 1, 0));
+                let before = timeline.musical(position - 1);
+                assert_eq!((before.bar, before.beat), (bar, BEATS_PER_BAR as usize));
+                assert_eq!(
+                    timeline.position_of_musical(musical(bar + 1, 1, 0)),
+                    position
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn positions_go_beyond_32_bits() {
+        let mut timeline = timeline_with_tempo_change();
+        timeline.locate(u32::MAX as u64);
+        timeline.advance(10);
+        assert_eq!(timeline.position(), u32::MAX as u64 + 10);
+
+        // One bar at 120 BPM, then 99 999 bars at 60 BPM
+        let bar = 100_000;
+        let position = 96000 + (bar as u64 - 1) * 192000;
+        assert!(position > u32::MAX as u64);
+        assert_eq!(timeline.bar_position(bar), position);
+        assert_eq!(timeline.musical(position), musical(bar + 1, 1, 0));
+        for position in [position, position + 1, 5_000_000_000, u32::MAX as u64 * 4] {
+            assert_eq!(
+                timeline.position_of_beats(timeline.beats(position)),
+                position
+            );
+        }
+    }
+}
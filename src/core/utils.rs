@@ -1,28 +1,376 @@
-use crate::core::audio_model::Input;
-use crate::features::Tape;
+use crate::features::{
+    Arrangement, AutomationMode, DuckerSettings, InputSettings, ModulationSettings, SynthSettings,
+    Tape, TapeMix,
+};
 use cpal::traits::DeviceTrait;
-use cpal::{Device, Stream, StreamConfig};
-use ringbuf::{traits::*, HeapCons, HeapProd};
+use cpal::{
+    BuildStreamError, Device, FromSample, SampleFormat, SampleRate, SizedSample, Stream,
+    StreamConfig, SupportedStreamConfig, SupportedStreamConfigRange,
+};
+use ringbuf::{traits::*, HeapProd};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub const TAPE_COUNT: usize = 8;
+pub const TAPE_CHANNELS: usize = 2; // tapes and the master bus are stereo
 pub const SAMPLE_GRAPH_SIZE: usize = 100;
 pub const A_FREQ: f32 = 440.0;
 pub const C_FREQ: f32 = 523.25;
-pub const CHANNELS: u16 = 4;
-pub const SAMPLE_RATE: u32 = 44100;
 pub const BITS_PER_SAMPLE: u16 = 16;
+pub const MAX_BLOCK_FRAMES: usize = 4096;
+pub const MAX_PRE_ROLL: f32 = 0.5; // seconds
+pub const CAPTURE_LOOP_COUNT: usize = 4; // loops the capture buffer holds
+pub const MAX_FREE_LOOP_LENGTH: f32 = 60.0; // seconds
+pub const MAX_LOOP_MULTIPLE: usize = 4; // a tape is at most this many loops long
+pub const MAX_LOOP_DIVISOR: usize = 4; // a tape is at least this fraction of a loop
+pub const MAX_SCHEDULED_ACTIONS: usize = 64;
+pub const ACTION_QUEUE_SIZE: usize = 16;
+pub const JOB_QUEUE_SIZE: usize = 64;
+/// Jobs the audio thread holds on to while the job queue is full
+/// New actions wait while there are any, so only the scheduled actions
+/// and the buffers that come back from the Worker can add to them
+pub const MAX_PENDING_JOBS: usize = 512;
+pub const MAX_RECORDINGS: usize = 4; // tapes that can be recorded at once
+pub const PUNCH_FADE_LENGTH: f32 = 0.005; // seconds
+pub const SCENE_COUNT: usize = 8;
+pub const MAX_TAKE_SAMPLES: usize = 64 * 1024 * 1024; // 256 MB of takes for all tapes
+pub const TICKS_PER_BEAT: usize = 960;
 
+/// One buffer per channel, tapes are stored planar
+pub type TapeAudio = [Vec<f32>; TAPE_CHANNELS];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Latency {
+    /// Input that is buffered until it is mixed, at least one block
+    /// The latency of the devices and their drivers comes on top of this
+    pub frames: usize,
+    pub milliseconds: f32,
+    pub overruns: u32,
+    pub underruns: u32,
+}
+
+/// How an armed recording is triggered and when it ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordSettings {
+    pub threshold: f32, // dB
+    pub pre_roll: f32,  // seconds, at most MAX_PRE_ROLL
+    pub is_one_loop: bool,
+    /// When a recording ends, the next empty tape is recorded from the next loop boundary
+    pub is_auto_advance: bool,
+    /// Loops of the primary tape until auto-advance moves on, 0 waits until the recording is stopped
+    pub auto_advance_loop_count: usize,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self {
+            threshold: -30.0,
+            pre_roll: 0.05,
+            is_one_loop: false,
+            is_auto_advance: false,
+            auto_advance_loop_count: 0,
+        }
+    }
+}
+
+/// Only this part of the tape is overwritten when it is recorded
+/// In beats from the start of the tape, beat 3 of bar 2 is 6 to 7
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PunchRegion {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Default for PunchRegion {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: 4.0,
+        }
+    }
+}
+
+/// Where the main mix and the cue mix go, and what the performer hears in the cue mix
+/// Output pairs are counted from 0, pair 1 is the output channels 3/4
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputSettings {
+    pub main_pair: usize,
+    /// The metronome is only in the cue mix if there is one
+    pub cue_pair: Option<usize>,
+    pub cue_tape_level: f32,
+    pub cue_metronome_level: f32,
+    pub cue_input_level: f32,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            main_pair: 0,
+            cue_pair: None,
+            cue_tape_level: 1.0,
+            cue_metronome_level: 1.0,
+            cue_input_level: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TransportState {
+    #[default]
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// Input channels a tape records from, channels are counted from 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputRoute {
+    /// One channel on both sides of the tape
+    Mono(usize),
+    /// This channel on the left and the next one on the right
+    Stereo(usize),
+    /// The built-in synth, it comes after the channels of the input device
+    Synth,
+}
+
+impl Default for InputRoute {
+    fn default() -> Self {
+        InputRoute::Stereo(0)
+    }
+}
+
+impl InputRoute {
+    /// The input channel of each side, devices with fewer channels use their last channel
+    /// `input_channel_count` is the channel count of the device, without the synth
+    pub fn channels(&self, input_channel_count: usize) -> [usize; TAPE_CHANNELS] {
+        let last = input_channel_count - 1;
+        match *self {
+            InputRoute::Mono(channel) => [channel.min(last); TAPE_CHANNELS],
+            InputRoute::Stereo(channel) => [channel.min(last), (channel + 1).min(last)],
+            InputRoute::Synth => [input_channel_count, input_channel_count + 1],
+        }
+    }
+}
+
+impl std::fmt::Display for InputRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputRoute::Mono(channel) => write!(f, "in {}", channel + 1),
+            InputRoute::Stereo(channel) => write!(f, "in {}/{}", channel + 1, channel + 2),
+            InputRoute::Synth => write!(f, "synth"),
+        }
+    }
+}
+
+/// Bars, beats and ticks, counted from 1 like on a tape machine's counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicalPosition {
+    pub bar: usize,
+    pub beat: usize,
+    pub tick: usize,
+}
+
+impl std::fmt::Display for MusicalPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{:03}", self.bar, self.beat, self.tick)
+    }
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+/// Only whole multiples and simple divisions of the loop keep the tapes in sync
+#[allow(clippy::manual_is_multiple_of)]
+pub fn is_supported_tape_length(tape_length: usize, length: usize) -> bool {
+    if length >= tape_length {
+        length % tape_length == 0 && length / tape_length <= MAX_LOOP_MULTIPLE
+    } else {
+        length > 0 && tape_length % length == 0 && MAX_LOOP_DIVISOR % (tape_length / length) == 0
+    }
+}
+
+/// Messages are moved through a preallocated ring buffer, boxing the large ones would
+/// allocate on the audio thread
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum ModulMessage {
-    AudioIndex(usize),
+    /// Timeline position in frames
+    Position(u64),
+    Transport(TransportState),
+    TapeLengths([usize; TAPE_COUNT]),
     Recording(bool),
+    Armed(bool),
     RecordingPlayback(bool),
     PlayThrough(bool),
     ShowBeat(bool),
-    BeatIndex(u32),
+    BeatIndex(u64),
     SampleAverages([f32; TAPE_COUNT + 1]),
     SamplesForGraphs([[f32; SAMPLE_GRAPH_SIZE]; TAPE_COUNT]),
+    Latency(Latency),
+    /// The first free running recording has set the loop length and the tempo
+    LoopDefined {
+        tape_length: usize,
+        bpm: f64,
+    },
+    /// Peaks of the left and right side of the master bus
+    MasterLevel([f32; TAPE_CHANNELS]),
+    /// How far every tape and then the master bus is ducked, in dB
+    GainReductions([f32; TAPE_COUNT + 1]),
+    PrimaryTape(usize),
+    /// Volume, pan, mute and solo of every tape, scenes change them all at once
+    TapeMixes([TapeMix; TAPE_COUNT]),
+    /// The section of the arrangement that is playing in song mode
+    Section(Option<usize>),
+    /// The automation lane of a tape, None if it is empty
+    AutomationGraph {
+        id: usize,
+        points: Option<[f32; SAMPLE_GRAPH_SIZE]>,
+    },
+    /// Numbers of the takes that are kept for a tape and the one that is playing
+    Takes {
+        id: usize,
+        numbers: Vec<usize>,
+        active: Option<usize>,
+    },
+    InputLevel {
+        peak: f32,
+        is_gate_open: bool,
+    },
+    Log(LogMessage),
+}
+
+/// Formatted on the UI thread, so logging from the audio thread doesn't allocate
+#[derive(Debug, Clone, Copy)]
+pub enum LogMessage {
+    MergingTapes {
+        primary_tape: usize,
+        secondary_tapes: [bool; TAPE_COUNT],
+    },
+    Cleared(usize),
+    ClearedAll,
+    RecordingTapeNotReady,
+    LoopDefined {
+        bpm: f64,
+    },
+    Captured {
+        id: usize,
+        bar_count: usize,
+    },
+    WritingTapeNotReady,
+    /// Jobs wait for the Worker, new actions wait for the jobs
+    WorkerIsBusy,
+    /// The tape is now `multiple / divisor` loops long
+    Resized {
+        id: usize,
+        multiple: usize,
+        divisor: usize,
+    },
+    TapeLengthNotSupported(usize),
+    TooManyScheduledActions,
+    TooManyRecordings,
+    NoEmptyTape,
+    SceneStored(usize),
+    SceneRecalled(usize),
+    SceneIsEmpty(usize),
+    ArrangementIsEmpty,
+    SongEnded,
+    Rendered {
+        bar_count: usize,
+        stem_count: usize,
+    },
+    TakeDropped {
+        id: usize,
+        number: usize,
+    },
+    Flattened {
+        id: usize,
+        number: usize,
+    },
+    /// A file in out/ couldn't be written, the buffer is kept
+    WriteFailed {
+        name: &'static str,
+        error: std::io::ErrorKind,
+    },
+}
+
+impl std::fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogMessage::MergingTapes {
+                primary_tape,
+                secondary_tapes,
+            } => write!(
+                f,
+                "Merging tapes, primary: {}, secondary: {:?}",
+                primary_tape, secondary_tapes
+            ),
+            LogMessage::Cleared(id) => write!(f, "Cleared tape {}", id + 1),
+            LogMessage::ClearedAll => write!(f, "Cleared all tapes"),
+            LogMessage::RecordingTapeNotReady => write!(f, "Recording tape is not ready yet"),
+            LogMessage::LoopDefined { bpm } => write!(f, "Loop is defined at {:0.2} BPM", bpm),
+            LogMessage::Captured { id, bar_count } => {
+                write!(f, "Captured the last {} bars to tape {}", bar_count, id + 1)
+            }
+            LogMessage::WritingTapeNotReady => write!(f, "Still writing to disc"),
+            LogMessage::WorkerIsBusy => write!(f, "Worker is busy, actions are waiting for it"),
+            LogMessage::Resized {
+                id,
+                multiple,
+                divisor,
+            } => write!(
+                f,
+                "Tape {} is {}/{} loops long now",
+                id + 1,
+                multiple,
+                divisor
+            ),
+            LogMessage::TapeLengthNotSupported(id) => {
+                write!(f, "Tape {} can't be resized any further", id + 1)
+            }
+            LogMessage::TooManyScheduledActions => {
+                write!(f, "Too many actions are waiting, an action is dropped")
+            }
+            LogMessage::TakeDropped { id, number } => write!(
+                f,
+                "Take {} of tape {} is dropped, takes are using too much memory",
+                number,
+                id + 1
+            ),
+            LogMessage::Flattened { id, number } => {
+                write!(f, "Comp of tape {} is flattened to take {}", id + 1, number)
+            }
+            LogMessage::SceneStored(index) => write!(f, "Stored scene {}", index + 1),
+            LogMessage::SceneRecalled(index) => write!(f, "Recalled scene {}", index + 1),
+            LogMessage::SceneIsEmpty(index) => write!(f, "Scene {} is empty", index + 1),
+            LogMessage::ArrangementIsEmpty => write!(f, "The arrangement has no sections"),
+            LogMessage::SongEnded => write!(f, "The song has ended"),
+            LogMessage::Rendered {
+                bar_count,
+                stem_count: 0,
+            } => write!(f, "Rendered the song, {} bars long", bar_count),
+            LogMessage::Rendered {
+                bar_count,
+                stem_count,
+            } => write!(
+                f,
+                "Rendered the song, {} bars long, and {} stems",
+                bar_count, stem_count
+            ),
+            LogMessage::WriteFailed { name, error } => {
+                write!(f, "Couldn't write out/{}.wav: {}", name, error)
+            }
+            LogMessage::NoEmptyTape => write!(f, "There is no empty tape to advance to"),
+            LogMessage::TooManyRecordings => {
+                write!(f, "Only {} tapes can be recorded at once", MAX_RECORDINGS)
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -31,6 +379,14 @@ pub enum ModulAction {
     SelectSecondaryTape(usize),
     MergeTapes,
     Record,
+    ArmRecord,
+    /// Commits the last given number of bars from the capture buffer
+    Capture(usize),
+    /// Repeats the primary tape so it is this many times longer
+    MultiplyTape(usize),
+    /// Cuts the primary tape down to this fraction
+    DivideTape(usize),
+    SetRecordSettings(RecordSettings),
     RecordPlayback,
     PlayThrough,
     Write,
@@ -40,63 +396,306 @@ pub enum ModulAction {
     ToggleSolo,
     VolumeUp,
     VolumeDown,
+    PanLeft,
+    PanRight,
     StartMetronome,
     StopMetronome,
+    SetInputSettings(InputSettings),
+    SetSynth(SynthSettings),
+    /// `velocity` is from 0 to 1
+    NoteOn {
+        note: u8,
+        velocity: f32,
+    },
+    NoteOff(u8),
+    SetOutputSettings(OutputSettings),
+    /// Keeps the volume, pan, mute and solo of every tape
+    StoreScene(usize),
+    /// Fades from the current mix to the scene in `fade_time` seconds
+    RecallScene {
+        index: usize,
+        fade_time: f32,
+    },
+    SetAutomationMode {
+        id: usize,
+        mode: AutomationMode,
+    },
+    ClearAutomation(usize),
+    SetModulation(Box<ModulationSettings>),
+    /// `target` is a tape, TAPE_COUNT is the master bus
+    SetDucker {
+        target: usize,
+        settings: DuckerSettings,
+    },
+    SetArrangement(Box<Arrangement>),
+    /// The arrangement plays from the first bar
+    SetSongMode(bool),
+    /// Mixes the arrangement to a file, with the tapes as they are now
+    /// With stems every tape that isn't empty is also written to a file of its own
+    RenderArrangement {
+        with_stems: bool,
+    },
+    SelectTake {
+        id: usize,
+        number: usize,
+    },
+    /// The take of every bar, bars without a take keep what the tape has now
+    FlattenComp {
+        id: usize,
+        comp: Vec<Option<usize>>,
+    },
+    /// Recordings of the tape only overwrite the region, None records the whole tape
+    SetPunchRegion {
+        id: usize,
+        region: Option<PunchRegion>,
+    },
+    SetInputRoute {
+        id: usize,
+        route: InputRoute,
+    },
+    /// The tape is recorded together with the primary tape
+    ToggleRecordEnabled(usize),
+    Play,
+    /// Keeps the position, recording stops
+    Pause,
+    /// Recording stops and the position returns to the first bar
+    Stop,
+    /// Jumps to a bar, counted from 0, bars after the loop are in multiplied tapes
+    Locate(usize),
 }
 
+/// When an action is applied, the audio thread applies it on exactly that sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timestamp {
+    /// At the start of the next output block
+    Now,
+    /// Frames since the output stream started
+    /// Frame and Musical are for replaying actions, the UI doesn't schedule them yet
+    #[allow(dead_code)]
+    Frame(u64),
+    /// The audio thread finds the next beat or bar when the action comes in,
+    /// the position the UI has is always a little behind
+    NextBeat,
+    NextBar,
+    /// The next time the loop gets to this bar and beat, both start at 0
+    #[allow(dead_code)]
+    Musical {
+        bar: usize,
+        beat: usize,
+    },
+}
+
+/// Record, mute and solo can wait for the next beat or bar
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Quantize {
+    #[default]
+    Off,
+    Beat,
+    Bar,
+}
+
+#[derive(Debug)]
+pub struct ScheduledAction {
+    pub action: ModulAction,
+    pub timestamp: Timestamp,
+}
+
+/// Sample formats the streams can convert to and from f32
+pub fn is_supported_sample_format(sample_format: SampleFormat) -> bool {
+    matches!(
+        sample_format,
+        SampleFormat::I8
+            | SampleFormat::I16
+            | SampleFormat::I32
+            | SampleFormat::I64
+            | SampleFormat::U8
+            | SampleFormat::U16
+            | SampleFormat::U32
+            | SampleFormat::U64
+            | SampleFormat::F32
+            | SampleFormat::F64
+    )
+}
+
+/// The default config of the device if its sample format is supported, otherwise the first
+/// supported one, at the default sample rate if the device allows it
+pub fn supported_config(
+    default_config: SupportedStreamConfig,
+    configs: impl Iterator<Item = SupportedStreamConfigRange>,
+) -> Option<SupportedStreamConfig> {
+    if is_supported_sample_format(default_config.sample_format()) {
+        return Some(default_config);
+    }
+    let sample_rate = default_config.sample_rate();
+    let mut configs = configs.filter(|config| is_supported_sample_format(config.sample_format()));
+    configs.next().map(|config| {
+        if (config.min_sample_rate()..=config.max_sample_rate()).contains(&sample_rate) {
+            config.with_sample_rate(sample_rate)
+        } else {
+            config.with_max_sample_rate()
+        }
+    })
+}
+
+/// `config` if it runs at `sample_rate`, otherwise a supported config that can,
+/// one with the same channel count and sample format is picked first
+pub fn config_at_sample_rate(
+    config: SupportedStreamConfig,
+    configs: impl Iterator<Item = SupportedStreamConfigRange>,
+    sample_rate: SampleRate,
+) -> Option<SupportedStreamConfig> {
+    if config.sample_rate() == sample_rate {
+        return Some(config);
+    }
+    let mut fallback = None;
+    for range in configs.filter(|range| {
+        is_supported_sample_format(range.sample_format())
+            && (range.min_sample_rate()..=range.max_sample_rate()).contains(&sample_rate)
+    }) {
+        if range.channels() == config.channels() && range.sample_format() == config.sample_format()
+        {
+            return Some(range.with_sample_rate(sample_rate));
+        }
+        fallback.get_or_insert(range);
+    }
+    fallback.map(|range| range.with_sample_rate(sample_rate))
+}
+
+/// Frames the input callback couldn't push because the output fell behind are counted
+/// in `dropped_frames`, the UI reads it
 pub fn create_input_stream_live(
     input_device: &Device,
     config: &StreamConfig,
-    tape_length: usize,
-    mut producer: HeapProd<Input>,
-) -> Stream {
-    let mut index = 0;
-    let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
-        let mut consumer_fell_behind = false;
-        for &sample in data {
-            if producer.try_push(Input { index, sample }).is_err() {
-                consumer_fell_behind = true;
-            }
-
-            index += 1;
-            if index == tape_length {
-                index = 0;
-            }
+    sample_format: SampleFormat,
+    producer: HeapProd<f32>,
+    dropped_frames: Arc<AtomicUsize>,
+) -> Result<Stream, BuildStreamError> {
+    match sample_format {
+        SampleFormat::I8 => {
+            build_input_stream::<i8>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::I16 => {
+            build_input_stream::<i16>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::I32 => {
+            build_input_stream::<i32>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::I64 => {
+            build_input_stream::<i64>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::U8 => {
+            build_input_stream::<u8>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::U16 => {
+            build_input_stream::<u16>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::U32 => {
+            build_input_stream::<u32>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::U64 => {
+            build_input_stream::<u64>(input_device, config, producer, dropped_frames)
+        }
+        SampleFormat::F32 => {
+            build_input_stream::<f32>(input_device, config, producer, dropped_frames)
         }
+        SampleFormat::F64 => {
+            build_input_stream::<f64>(input_device, config, producer, dropped_frames)
+        }
+        _ => Err(BuildStreamError::StreamConfigNotSupported),
+    }
+}
+
+/// Device samples are converted to f32, which is the format modul works with internally
+/// Only whole frames are pushed so the consumer never gets out of step with the channels
+fn build_input_stream<T>(
+    input_device: &Device,
+    config: &StreamConfig,
+    mut producer: HeapProd<f32>,
+    dropped_frames: Arc<AtomicUsize>,
+) -> Result<Stream, BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channel_count = config.channels as usize;
+    let input_data_fn = move |data: &[T], _: &cpal::InputCallbackInfo| {
+        let writable = producer.vacant_len().min(data.len());
+        let writable = writable - writable % channel_count;
+        producer.push_iter(data[..writable].iter().map(|sample| sample_to_f32(*sample)));
 
-        if consumer_fell_behind {
-            println!("[AudioIn] Consumer fell behind, not enough space in buffer.");
+        if writable < data.len() {
+            dropped_frames.fetch_add((data.len() - writable) / channel_count, Ordering::Relaxed);
         }
     };
 
-    input_device
-        .build_input_stream(config, input_data_fn, err_fn, None)
-        .unwrap()
+    input_device.build_input_stream(config, input_data_fn, err_fn, None)
+}
+
+/// `render` is called from the output callback and fills interleaved f32 blocks
+/// of at most MAX_BLOCK_FRAMES frames
+pub fn create_output_stream_live<R>(
+    output_device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    render: R,
+) -> Result<Stream, BuildStreamError>
+where
+    R: FnMut(&mut [f32]) + Send + 'static,
+{
+    match sample_format {
+        SampleFormat::I8 => build_output_stream::<i8, R>(output_device, config, render),
+        SampleFormat::I16 => build_output_stream::<i16, R>(output_device, config, render),
+        SampleFormat::I32 => build_output_stream::<i32, R>(output_device, config, render),
+        SampleFormat::I64 => build_output_stream::<i64, R>(output_device, config, render),
+        SampleFormat::U8 => build_output_stream::<u8, R>(output_device, config, render),
+        SampleFormat::U16 => build_output_stream::<u16, R>(output_device, config, render),
+        SampleFormat::U32 => build_output_stream::<u32, R>(output_device, config, render),
+        SampleFormat::U64 => build_output_stream::<u64, R>(output_device, config, render),
+        SampleFormat::F32 => build_output_stream::<f32, R>(output_device, config, render),
+        SampleFormat::F64 => build_output_stream::<f64, R>(output_device, config, render),
+        _ => Err(BuildStreamError::StreamConfigNotSupported),
+    }
 }
 
-pub fn create_output_stream_live(
+/// Mixed f32 samples are converted to the device format right before they are handed over
+fn build_output_stream<T, R>(
     output_device: &Device,
     config: &StreamConfig,
-    mut consumer: HeapCons<f32>,
-) -> Stream {
-    let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-        for sample in data {
-            *sample = consumer.try_pop().unwrap_or(0.0);
-        }
-        // Consumer capacity is equal to 8192, I don't know what I intented to achieve here
-        // But this got rid of the glitchy sound
-        if consumer.occupied_len() > 4096 {
-            consumer.skip(4096);
-            println!(
-                "Skipped 4096 samples, consumer: {}",
-                consumer.occupied_len()
-            );
+    mut render: R,
+) -> Result<Stream, BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+    R: FnMut(&mut [f32]) + Send + 'static,
+{
+    let mut block = vec![0.0; MAX_BLOCK_FRAMES * config.channels as usize];
+    let output_data_fn = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+        for chunk in data.chunks_mut(block.len()) {
+            let block = &mut block[..chunk.len()];
+            render(block);
+            for (sample, mixed) in chunk.iter_mut().zip(block.iter()) {
+                *sample = sample_from_f32(*mixed);
+            }
         }
     };
 
-    output_device
-        .build_output_stream(config, output_data_fn, err_fn, None)
-        .unwrap()
+    output_device.build_output_stream(config, output_data_fn, err_fn, None)
+}
+
+/// A device sample as f32, from -1 to 1
+pub fn sample_to_f32<T>(sample: T) -> f32
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    sample.to_sample::<f32>()
+}
+
+/// A mixed sample in the device format, anything over full scale is clipped
+pub fn sample_from_f32<T>(sample: f32) -> T
+where
+    T: SizedSample + FromSample<f32>,
+{
+    T::from_sample(sample.clamp(-1.0, 1.0))
 }
 
 pub fn err_fn(err: cpal::StreamError) {
@@ -107,9 +706,7 @@ pub fn _merge_tapes(tapes: &[Tape<f32>], tape_length: usize) -> Tape<f32> {
     let mut sum_tape: Tape<f32> = Tape::new(0.0, tape_length);
 
     for tape in tapes {
-        for (sum, sample) in sum_tape.audio.iter_mut().zip(tape.audio.iter()) {
-            *sum += *sample;
-        }
+        sum_tape.add(tape.audio.clone());
     }
 
     sum_tape
@@ -122,7 +719,7 @@ pub fn load_image(path: &Path) -> image::DynamicImage {
 }
 
 pub fn load_image_for_ui(path: &Path) -> Result<egui::ColorImage, image::ImageError> {
-    let image = image::io::Reader::open(path)?.decode()?;
+    let image = image::ImageReader::open(path)?.decode()?;
     let size = [image.width() as _, image.height() as _];
     let image_buffer = image.to_rgba8();
     let pixels = image_buffer.as_flat_samples();
@@ -132,33 +729,247 @@ pub fn load_image_for_ui(path: &Path) -> Result<egui::ColorImage, image::ImageEr
     ))
 }
 
-pub fn _write_tape(tape: &Tape<f32>, name: &str) {
-    let spec = hound::WavSpec {
-        channels: CHANNELS,               // TODO: Fix this hardcoded value
-        sample_rate: SAMPLE_RATE,         // TODO: Fix this hardcoded value
-        bits_per_sample: BITS_PER_SAMPLE, // TODO: Fix this hardcoded value
-        sample_format: hound::SampleFormat::Int,
-    };
+/// Both sides of the tape at the sample rate of the stream that recorded it
+pub fn _write_tape(tape: &Tape<f32>, name: &str, sample_rate: u32) -> Result<(), hound::Error> {
+    let mut writer = create_wav(name, sample_rate)?;
+    for frame in 0..tape.length() {
+        for channel in &tape.audio {
+            writer.write_sample(sample_from_f32::<i16>(channel[frame]))?;
+        }
+    }
 
-    let mut writer = hound::WavWriter::create(format!("out/{}.wav", name), spec).unwrap();
-    for frame in tape.audio.iter() {
-        let sample = frame;
-        let amplitude = i16::MAX as f32;
-        writer.write_sample((sample * amplitude) as i16).unwrap();
+    writer.finalize()
+}
+
+/// Interleaved stereo at the sample rate of the output stream, clipped to 16 bits
+pub fn write(buffer: &[f32], name: &str, sample_rate: u32) -> Result<(), hound::Error> {
+    let mut writer = create_wav(name, sample_rate)?;
+    for sample in buffer.iter() {
+        writer.write_sample(sample_from_f32::<i16>(*sample))?;
     }
+
+    writer.finalize()
 }
 
-pub fn write(buffer: &[f32], name: &str) {
+fn create_wav(
+    name: &str,
+    sample_rate: u32,
+) -> Result<hound::WavWriter<std::io::BufWriter<std::fs::File>>, hound::Error> {
     let spec = hound::WavSpec {
-        channels: CHANNELS,               // TODO: Fix this hardcoded value
-        sample_rate: SAMPLE_RATE,         // TODO: Fix this hardcoded value
-        bits_per_sample: BITS_PER_SAMPLE, // TODO: Fix this hardcoded value
+        channels: TAPE_CHANNELS as u16,
+        sample_rate,
+        bits_per_sample: BITS_PER_SAMPLE,
         sample_format: hound::SampleFormat::Int,
     };
+    std::fs::create_dir_all("out")?;
 
-    let mut writer = hound::WavWriter::create(format!("out/{}.wav", name), spec).unwrap();
-    for sample in buffer.iter() {
-        let amplitude = i16::MAX as f32;
-        writer.write_sample((sample * amplitude) as i16).unwrap();
+    hound::WavWriter::create(format!("out/{}.wav", name), spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn i16_converts_to_f32() {
+        assert_eq!(sample_to_f32(i16::MIN), -1.0);
+        assert_eq!(sample_to_f32(0i16), 0.0);
+        assert_eq!(sample_to_f32(i16::MAX), i16::MAX as f32 / 32768.0);
+    }
+
+    #[test]
+    fn f32_converts_to_i16() {
+        assert_eq!(sample_from_f32::<i16>(-1.0), i16::MIN);
+        assert_eq!(sample_from_f32::<i16>(0.0), 0);
+        assert_eq!(sample_from_f32::<i16>(1.0), i16::MAX);
+        assert_eq!(sample_from_f32::<i16>(0.5), 16384);
+    }
+
+    #[test]
+    fn f32_is_clipped_to_i16() {
+        assert_eq!(sample_from_f32::<i16>(1.5), i16::MAX);
+        assert_eq!(sample_from_f32::<i16>(-1.5), i16::MIN);
+        assert_eq!(sample_from_f32::<i16>(f32::INFINITY), i16::MAX);
+    }
+
+    #[test]
+    fn u16_converts_to_f32() {
+        assert_eq!(sample_to_f32(u16::MIN), -1.0);
+        assert_eq!(sample_to_f32(32768u16), 0.0);
+        assert_eq!(sample_to_f32(u16::MAX), 32767.0 / 32768.0);
+    }
+
+    #[test]
+    fn f32_converts_to_u16() {
+        assert_eq!(sample_from_f32::<u16>(-1.0), u16::MIN);
+        assert_eq!(sample_from_f32::<u16>(0.0), 32768);
+        assert_eq!(sample_from_f32::<u16>(1.0), u16::MAX);
+    }
+
+    #[test]
+    fn f32_is_clipped_to_u16() {
+        assert_eq!(sample_from_f32::<u16>(2.0), u16::MAX);
+        assert_eq!(sample_from_f32::<u16>(-2.0), u16::MIN);
+    }
+
+    #[test]
+    fn f32_passes_through() {
+        assert_eq!(sample_to_f32(0.25f32), 0.25);
+        assert_eq!(sample_to_f32(-1.0f32), -1.0);
+        assert_eq!(sample_from_f32::<f32>(0.25), 0.25);
+        assert_eq!(sample_from_f32::<f32>(1.0), 1.0);
+        assert_eq!(sample_from_f32::<f32>(-1.0), -1.0);
+    }
+
+    #[test]
+    fn f32_is_clipped_to_f32() {
+        assert_eq!(sample_from_f32::<f32>(1.5), 1.0);
+        assert_eq!(sample_from_f32::<f32>(-1.5), -1.0);
+    }
+
+    #[test]
+    fn i8_converts_to_f32() {
+        assert_eq!(sample_to_f32(i8::MIN), -1.0);
+        assert_eq!(sample_to_f32(0i8), 0.0);
+        assert_eq!(sample_to_f32(i8::MAX), 127.0 / 128.0);
+        assert_eq!(sample_from_f32::<i8>(-1.0), i8::MIN);
+        assert_eq!(sample_from_f32::<i8>(0.5), 64);
+        assert_eq!(sample_from_f32::<i8>(1.0), i8::MAX);
+        assert_eq!(sample_from_f32::<i8>(2.0), i8::MAX);
+        assert_eq!(sample_from_f32::<i8>(-2.0), i8::MIN);
+    }
+
+    #[test]
+    fn u8_converts_to_f32() {
+        assert_eq!(sample_to_f32(u8::MIN), -1.0);
+        assert_eq!(sample_to_f32(128u8), 0.0);
+        assert_eq!(sample_to_f32(u8::MAX), 127.0 / 128.0);
+        assert_eq!(sample_from_f32::<u8>(-1.0), u8::MIN);
+        assert_eq!(sample_from_f32::<u8>(0.0), 128);
+        assert_eq!(sample_from_f32::<u8>(1.0), u8::MAX);
+        assert_eq!(sample_from_f32::<u8>(2.0), u8::MAX);
+        assert_eq!(sample_from_f32::<u8>(-2.0), u8::MIN);
+    }
+
+    #[test]
+    fn i32_and_i64_convert_to_f32() {
+        assert_eq!(sample_to_f32(i32::MIN), -1.0);
+        assert_eq!(sample_to_f32(0i32), 0.0);
+        assert_eq!(sample_to_f32(i32::MAX), 1.0);
+        assert_eq!(sample_from_f32::<i32>(-1.0), i32::MIN);
+        assert_eq!(sample_from_f32::<i32>(0.5), 1 << 30);
+        assert_eq!(sample_from_f32::<i32>(1.0), i32::MAX);
+        assert_eq!(sample_from_f32::<i32>(f32::INFINITY), i32::MAX);
+        assert_eq!(sample_from_f32::<i32>(-2.0), i32::MIN);
+
+        assert_eq!(sample_to_f32(i64::MIN), -1.0);
+        assert_eq!(sample_to_f32(0i64), 0.0);
+        assert_eq!(sample_to_f32(i64::MAX), 1.0);
+        assert_eq!(sample_from_f32::<i64>(-1.0), i64::MIN);
+        assert_eq!(sample_from_f32::<i64>(0.5), 1 << 62);
+        assert_eq!(sample_from_f32::<i64>(1.0), i64::MAX);
+        assert_eq!(sample_from_f32::<i64>(f32::INFINITY), i64::MAX);
+        assert_eq!(sample_from_f32::<i64>(-2.0), i64::MIN);
+    }
+
+    #[test]
+    fn u32_and_u64_convert_to_f32() {
+        assert_eq!(sample_to_f32(u32::MIN), -1.0);
+        assert_eq!(sample_to_f32(1u32 << 31), 0.0);
+        assert_eq!(sample_to_f32(u32::MAX), 1.0);
+        assert_eq!(sample_from_f32::<u32>(-1.0), u32::MIN);
+        assert_eq!(sample_from_f32::<u32>(0.0), 1 << 31);
+        assert_eq!(sample_from_f32::<u32>(1.0), u32::MAX);
+        assert_eq!(sample_from_f32::<u32>(2.0), u32::MAX);
+        assert_eq!(sample_from_f32::<u32>(-2.0), u32::MIN);
+
+        assert_eq!(sample_to_f32(u64::MIN), -1.0);
+        assert_eq!(sample_to_f32(1u64 << 63), 0.0);
+        assert_eq!(sample_to_f32(u64::MAX), 1.0);
+        assert_eq!(sample_from_f32::<u64>(-1.0), u64::MIN);
+        assert_eq!(sample_from_f32::<u64>(0.0), 1 << 63);
+        assert_eq!(sample_from_f32::<u64>(1.0), u64::MAX);
+        assert_eq!(sample_from_f32::<u64>(2.0), u64::MAX);
+        assert_eq!(sample_from_f32::<u64>(-2.0), u64::MIN);
+    }
+
+    #[test]
+    fn f64_converts_to_f32() {
+        assert_eq!(sample_to_f32(0.25f64), 0.25);
+        assert_eq!(sample_to_f32(-1.0f64), -1.0);
+        assert_eq!(sample_from_f32::<f64>(0.25), 0.25);
+        assert_eq!(sample_from_f32::<f64>(1.5), 1.0);
+        assert_eq!(sample_from_f32::<f64>(-1.5), -1.0);
+    }
+
+    fn config_range(
+        channels: u16,
+        rates: [u32; 2],
+        format: SampleFormat,
+    ) -> SupportedStreamConfigRange {
+        let buffer_size = cpal::SupportedBufferSize::Unknown;
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(rates[0]),
+            SampleRate(rates[1]),
+            buffer_size,
+            format,
+        )
+    }
+
+    #[test]
+    fn input_config_follows_the_output_sample_rate() {
+        let config = config_range(2, [44100, 44100], SampleFormat::F32).with_max_sample_rate();
+        let configs = [
+            config_range(1, [8000, 96000], SampleFormat::F32),
+            config_range(2, [8000, 48000], SampleFormat::I16),
+            config_range(2, [8000, 96000], SampleFormat::F32),
+        ];
+        let at_rate = |sample_rate| {
+            config_at_sample_rate(config.clone(), configs.into_iter(), SampleRate(sample_rate))
+        };
+
+        // The same channel count and format are picked first
+        let matched = at_rate(48000).unwrap();
+        assert_eq!(matched.sample_rate(), SampleRate(48000));
+        assert_eq!(matched.channels(), 2);
+        assert_eq!(matched.sample_format(), SampleFormat::F32);
+        assert_eq!(at_rate(44100), Some(config.clone()));
+        assert!(at_rate(192000).is_none());
+
+        let configs = [config_range(1, [8000, 96000], SampleFormat::I32)];
+        let fallback = config_at_sample_rate(config, configs.into_iter(), SampleRate(48000));
+        assert_eq!(fallback.unwrap().channels(), 1);
+    }
+
+    /// The sample after it is converted to the device format and back
+    fn round_trip<T>(sample: f32) -> f32
+    where
+        T: SizedSample + FromSample<f32>,
+        f32: FromSample<T>,
+    {
+        sample_to_f32(sample_from_f32::<T>(sample))
+    }
+
+    #[test]
+    fn samples_round_trip_through_every_format() {
+        for sample in [-1.0, -0.99, -0.3, 0.0, 0.1, 0.7] {
+            // 8 bits only keep steps of 1/128
+            for round_tripped in [round_trip::<i8>(sample), round_trip::<u8>(sample)] {
+                assert!((round_tripped - sample).abs() <= 1.0 / 128.0);
+            }
+            for round_tripped in [
+                round_trip::<i16>(sample),
+                round_trip::<u16>(sample),
+                round_trip::<i32>(sample),
+                round_trip::<u32>(sample),
+                round_trip::<i64>(sample),
+                round_trip::<u64>(sample),
+                round_trip::<f32>(sample),
+                round_trip::<f64>(sample),
+            ] {
+                assert!((round_tripped - sample).abs() <= 1.0 / 32768.0);
+            }
+        }
     }
 }
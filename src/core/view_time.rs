@@ -1,9 +1,11 @@
 use std::time::Instant;
 
+#[allow(dead_code)]
 pub struct ViewTime {
     start: Instant,
 }
 
+#[allow(dead_code)]
 impl ViewTime {
     pub fn new() -> Self {
         ViewTime {
@@ -0,0 +1,695 @@
+use crate::core::*;
+use crate::features::{
+    Arrangement, AutomationLane, AutomationMode, CaptureBuffer, DuckerSettings, ModulationSettings,
+    Punch, Takes, TapeMix, MODULATION_INTERVAL,
+};
+use ringbuf::{traits::*, HeapCons, HeapProd};
+use std::time::Duration;
+
+/// Files the stems of the song are written to, in out/
+const STEM_NAMES: [&str; TAPE_COUNT] = [
+    "song_tape_1",
+    "song_tape_2",
+    "song_tape_3",
+    "song_tape_4",
+    "song_tape_5",
+    "song_tape_6",
+    "song_tape_7",
+    "song_tape_8",
+];
+
+/// Work that allocates or touches whole tapes, so it can't run in the output callback
+pub enum Job {
+    /// A finished recording that replaces the content of the tape
+    Commit {
+        id: usize,
+        audio: TapeAudio,
+        punch: Option<Punch>,
+    },
+    /// The first free running recording, it sets the length of every tape
+    /// A recording tape for every tape that can be recorded at once is sent back
+    DefineLoop {
+        id: usize,
+        audio: TapeAudio,
+        tape_length: usize,
+    },
+    Merge {
+        primary_tape: usize,
+        secondary_tapes: [bool; TAPE_COUNT],
+    },
+    /// Copies `len` captured frames that end at `end` to the tape, repeated to fill it
+    Capture {
+        id: usize,
+        end: usize,
+        len: usize,
+        route: InputRoute,
+    },
+    Multiply {
+        id: usize,
+        factor: usize,
+    },
+    Divide {
+        id: usize,
+        divisor: usize,
+    },
+    SelectTake {
+        id: usize,
+        number: usize,
+    },
+    /// Bars are taken from the takes with short crossfades in between
+    Flatten {
+        id: usize,
+        comp: Vec<Option<usize>>,
+        bar_length: usize,
+        fade_length: usize,
+    },
+    /// Mixes every section of the arrangement like the audio thread would play it
+    Render(Box<Song>),
+    Clear(usize),
+    ClearAll,
+    Write(Vec<f32>),
+    /// Buffers that were swapped out, they are freed here instead of the audio thread
+    Recycle(TapeAudio),
+    RecycleCaptureBuffer(CaptureBuffer),
+    RecycleArrangement(Box<Arrangement>),
+    RecycleModulation(Box<ModulationSettings>),
+}
+
+/// Everything the Worker needs to render the arrangement
+/// Preallocated, the audio thread fills it in and gets it back once the file is written
+#[derive(Default)]
+pub struct Song {
+    pub arrangement: Arrangement,
+    /// Volume, pan, mute and solo of every tape when the song is rendered
+    pub mixes: [TapeMix; TAPE_COUNT],
+    pub scenes: [Option<[TapeMix; TAPE_COUNT]>; SCENE_COUNT],
+    pub bar_length: usize,
+    /// Lanes are one loop long
+    pub loop_length: usize,
+    pub automation_lanes: [AutomationLane; TAPE_COUNT],
+    pub automation_modes: [AutomationMode; TAPE_COUNT],
+    /// LFOs and envelope followers move the tapes like they do on the audio thread
+    pub modulation: ModulationSettings,
+    /// Of every tape and then the master bus
+    pub duckers: [DuckerSettings; TAPE_COUNT + 1],
+    pub with_stems: bool,
+}
+
+/// Finished buffers that the audio thread swaps in
+pub enum Done {
+    Tape {
+        id: usize,
+        audio: TapeAudio,
+        is_empty: bool,
+    },
+    RecordingTape(TapeAudio),
+    WritingTape(Vec<f32>),
+    Song(Box<Song>),
+    CaptureBuffer(CaptureBuffer),
+}
+
+/// Runs on its own thread and owns a copy of every tape, the audio thread
+/// only ever receives finished buffers from here
+pub struct Worker {
+    tape_length: usize,
+    /// Of the output stream, files are written at this rate
+    sample_rate: u32,
+    tape_model: TapeModel,
+    takes: Takes,
+    capture_buffer: CaptureBuffer,
+    samples_for_graphs: [[f32; SAMPLE_GRAPH_SIZE]; TAPE_COUNT],
+    job_consumer: HeapCons<Job>,
+    done_producer: HeapProd<Done>,
+    message_producer: HeapProd<ModulMessage>,
+}
+
+impl Worker {
+    pub fn new(
+        tape_length: usize,
+        sample_rate: u32,
+        capture_buffer: CaptureBuffer,
+        job_consumer: HeapCons<Job>,
+        done_producer: HeapProd<Done>,
+        message_producer: HeapProd<ModulMessage>,
+    ) -> Self {
+        Self {
+            tape_length,
+            sample_rate,
+            tape_model: TapeModel::new(tape_length),
+            takes: Takes::new(MAX_TAKE_SAMPLES),
+            capture_buffer,
+            samples_for_graphs: [[0.0; SAMPLE_GRAPH_SIZE]; TAPE_COUNT],
+            job_consumer,
+            done_producer,
+            message_producer,
+        }
+    }
+
+    pub fn run(mut self) {
+        loop {
+            self.handle_jobs();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Handles every job that is waiting in the queue
+    pub fn handle_jobs(&mut self) {
+        while let Some(job) = self.job_consumer.try_pop() {
+            self.handle(job);
+        }
+    }
+
+    fn handle(&mut self, job: Job) {
+        match job {
+            Job::Commit {
+                id,
+                mut audio,
+                punch,
+            } => {
+                // The recording tape fits the longest tape, only the part the tape plays is kept
+                let length = self.tape_model.tapes[id].length();
+                for (tape, recording) in self.tape_model.tapes[id].audio.iter_mut().zip(&mut audio)
+                {
+                    recording.truncate(length);
+                    match punch {
+                        Some(punch) => {
+                            punch.apply(tape, recording);
+                            // Sent back as the new tape, so it has to hold the whole tape
+                            recording.copy_from_slice(tape);
+                        }
+                        None => tape.copy_from_slice(recording),
+                    }
+                }
+                self.update_waveform(id);
+                self.send_tape_lengths();
+                self.add_take(id);
+                let is_empty = is_silent(&audio);
+                self.finish(Done::Tape {
+                    id,
+                    audio,
+                    is_empty,
+                });
+                self.finish(Done::RecordingTape(recording_tape(self.tape_length)));
+            }
+            Job::DefineLoop {
+                id,
+                audio,
+                tape_length,
+            } => {
+                // The other tapes keep their audio and their multiple of the loop,
+                // they start on the first bar of the new loop
+                let old_tape_length = self.tape_length;
+                self.tape_length = tape_length;
+                for tape in self.tape_model.tapes.iter_mut() {
+                    let length = tape.length();
+                    let length = if length >= old_tape_length {
+                        tape_length * (length / old_tape_length)
+                    } else if tape_length % (old_tape_length / length) == 0 {
+                        tape_length / (old_tape_length / length)
+                    } else {
+                        tape_length
+                    };
+                    for channel in tape.audio.iter_mut() {
+                        let old_length = channel.len();
+                        *channel = (0..length).map(|i| channel[i % old_length]).collect();
+                    }
+                }
+                for (tape, recording) in self.tape_model.tapes[id].audio.iter_mut().zip(&audio) {
+                    *tape = recording[..tape_length].to_vec();
+                }
+                // Takes of the old length don't fit the loop anymore
+                self.takes.clear();
+                for id in 0..TAPE_COUNT {
+                    self.update_tape(id);
+                    self.send_takes(id);
+                }
+                self.add_take(id);
+                self.capture_buffer = CaptureBuffer::new(
+                    self.capture_buffer.channel_count(),
+                    tape_length * CAPTURE_LOOP_COUNT,
+                );
+                self.finish(Done::CaptureBuffer(self.capture_buffer.clone()));
+                for _ in 0..MAX_RECORDINGS {
+                    self.finish(Done::RecordingTape(recording_tape(tape_length)));
+                }
+            }
+            Job::Merge {
+                primary_tape,
+                secondary_tapes,
+            } => {
+                for (id, is_secondary) in secondary_tapes.into_iter().enumerate() {
+                    if is_secondary && id != primary_tape {
+                        let audio = self.tape_model.tapes[id].audio.clone();
+                        self.tape_model.tapes[primary_tape].add(audio);
+                    }
+                }
+                self.update_tape(primary_tape);
+                self.set_active_take(primary_tape, None);
+            }
+            Job::Capture {
+                id,
+                end,
+                len,
+                route,
+            } => {
+                let capacity = self.capture_buffer.capacity();
+                let start = (end + capacity - len) % capacity;
+                // Capture indices line up with tape indices, so the bars keep their place in the loop
+                let offset = start % self.tape_length;
+                // The synth is captured after the channels of the input device
+                let input_channel_count = self.capture_buffer.channel_count() - TAPE_CHANNELS;
+                let channels = route.channels(input_channel_count);
+                for (audio, channel) in self.tape_model.tapes[id].audio.iter_mut().zip(channels) {
+                    for (i, sample) in audio.iter_mut().enumerate() {
+                        let position = (i + self.tape_length - offset) % self.tape_length % len;
+                        *sample = self.capture_buffer.read(channel, start + position);
+                    }
+                }
+                self.update_tape(id);
+                self.set_active_take(id, None);
+            }
+            Job::Multiply { id, factor } => {
+                let length = self.tape_model.tapes[id].length() * factor;
+                self.resize_tape(id, length);
+            }
+            Job::Divide { id, divisor } => {
+                let length = self.tape_model.tapes[id].length() / divisor;
+                self.resize_tape(id, length);
+            }
+            Job::SelectTake { id, number } => {
+                if let Some(audio) = self.takes.get(id, number) {
+                    self.tape_model.tapes[id].audio = audio.clone();
+                    self.update_tape(id);
+                    self.set_active_take(id, Some(number));
+                }
+            }
+            Job::Flatten {
+                id,
+                comp,
+                bar_length,
+                fade_length,
+            } => {
+                for (bar, number) in comp.into_iter().enumerate() {
+                    let Some(take) = number.and_then(|number| self.takes.get(id, number)) else {
+                        continue;
+                    };
+                    let punch = Punch {
+                        start: bar * bar_length,
+                        end: (bar + 1) * bar_length,
+                        fade_length,
+                    };
+                    for (tape, take) in self.tape_model.tapes[id].audio.iter_mut().zip(take) {
+                        punch.apply(tape, take);
+                    }
+                }
+                self.update_tape(id);
+                let number = self.add_take(id);
+                self.log(LogMessage::Flattened { id, number });
+            }
+            Job::Render(song) => {
+                let rendered = self.render(&song, None);
+                if self.write(&rendered, "song") {
+                    let mut stem_count = 0;
+                    if song.with_stems {
+                        for (id, name) in STEM_NAMES.into_iter().enumerate() {
+                            if is_silent(&self.tape_model.tapes[id].audio) {
+                                continue;
+                            }
+                            let stem = self.render(&song, Some(id));
+                            if !self.write(&stem, name) {
+                                break;
+                            }
+                            stem_count += 1;
+                        }
+                    }
+                    self.log(LogMessage::Rendered {
+                        bar_count: song.arrangement.bar_count(),
+                        stem_count,
+                    });
+                }
+                self.finish(Done::Song(song));
+            }
+            Job::Clear(id) => {
+                self.tape_model.tapes[id].clear(0.0);
+                self.update_tape(id);
+                self.set_active_take(id, None);
+            }
+            Job::ClearAll => {
+                for id in 0..TAPE_COUNT {
+                    self.tape_model.tapes[id].clear(0.0);
+                    self.update_tape(id);
+                    self.set_active_take(id, None);
+                }
+            }
+            Job::Write(writing_tape) => {
+                self.write(&writing_tape, "full");
+                self.finish(Done::WritingTape(writing_tape));
+            }
+            Job::Recycle(audio) => drop(audio),
+            Job::RecycleCaptureBuffer(capture_buffer) => drop(capture_buffer),
+            Job::RecycleArrangement(arrangement) => drop(arrangement),
+            Job::RecycleModulation(modulation) => drop(modulation),
+        }
+    }
+
+    /// Interleaved, the tapes play from the start of the song like they do on the timeline
+    /// Automation lanes play like they do on the audio thread, nothing is written to them
+    /// The tapes go through a TapeMixer like on the audio thread, so they are modulated and ducked
+    /// There is no input to follow
+    /// A stem is one tape on its own, duckers only listen to the tapes so the stems add up to the song
+    fn render(&self, song: &Song, stem: Option<usize>) -> Vec<f32> {
+        let Song {
+            arrangement,
+            mixes,
+            scenes,
+            bar_length,
+            loop_length,
+            automation_lanes,
+            automation_modes,
+            modulation,
+            duckers,
+            ..
+        } = song;
+        let (bar_length, loop_length) = (*bar_length, *loop_length);
+        let is_automated = automation_modes
+            .iter()
+            .any(|mode| *mode != AutomationMode::Off);
+        let tapes = &self.tape_model.tapes;
+        let mut song = Vec::with_capacity(arrangement.bar_count() * bar_length * TAPE_CHANNELS);
+        let mut bus: TapeAudio = std::array::from_fn(|_| vec![0.0; MAX_BLOCK_FRAMES]);
+        let mut peaks = [0.0; TAPE_COUNT];
+        let mut tape_mixer = TapeMixer::new(self.sample_rate, Box::new(*modulation));
+        for (target, settings) in duckers.iter().enumerate() {
+            tape_mixer.set_ducker(target, *settings);
+        }
+        let mut position = 0;
+        let mut end = 0;
+        for (section_index, section) in arrangement.sections().iter().enumerate() {
+            end += section.bar_count * bar_length;
+            while position < end {
+                // A block never runs over the end of a tape, like a segment on the audio thread
+                let mut len = (end - position).min(MAX_BLOCK_FRAMES);
+                for tape in tapes.iter() {
+                    len = len.min(tape.length() - position % tape.length());
+                }
+                let index = position % loop_length;
+                if is_automated {
+                    len = len.min(AutomationLane::frames_to_next_point(index, loop_length));
+                }
+                if tape_mixer.is_modulated() {
+                    len = len.min(MODULATION_INTERVAL);
+                }
+                let indices = std::array::from_fn(|id| position % tapes[id].length());
+                let beats = position as f64 * BEATS_PER_BAR as f64 / bar_length as f64;
+                tape_mixer.modulate(tapes, indices, len, 0.0, beats);
+                // Scenes are moved by the automation and the modulation too
+                let section_mixes = arrangement.mixes(section_index, *mixes, scenes);
+                let mixes: [TapeMix; TAPE_COUNT] = std::array::from_fn(|id| {
+                    if automation_modes[id] == AutomationMode::Off {
+                        section_mixes[id]
+                    } else {
+                        automation_lanes[id].read(section_mixes[id], index, loop_length)
+                    }
+                });
+                let mixes = tape_mixer.apply(mixes);
+                let mut gains = arrangement.gains(section_index, &mixes);
+                if let Some(stem) = stem {
+                    for side in gains.iter_mut() {
+                        for (id, gain) in side.iter_mut().enumerate() {
+                            if id != stem {
+                                *gain = 0.0;
+                            }
+                        }
+                    }
+                }
+                let [left, right] = &mut bus;
+                tape_mixer.mix(
+                    tapes,
+                    indices,
+                    &gains,
+                    [&mut left[..len], &mut right[..len]],
+                    &mut peaks,
+                );
+                for frame in 0..len {
+                    song.extend(bus.iter().map(|channel| channel[frame]));
+                }
+                position += len;
+            }
+        }
+
+        song
+    }
+
+    fn update_tape(&mut self, id: usize) {
+        self.update_waveform(id);
+        self.send_tape_lengths();
+        let audio = self.tape_model.tapes[id].audio.clone();
+        let is_empty = is_silent(&audio);
+        self.finish(Done::Tape {
+            id,
+            audio,
+            is_empty,
+        });
+    }
+
+    /// Multiplying repeats the content, dividing keeps the beginning of the tape
+    fn resize_tape(&mut self, id: usize, length: usize) {
+        if !is_supported_tape_length(self.tape_length, length) {
+            self.log(LogMessage::TapeLengthNotSupported(id));
+            return;
+        }
+        let resized: TapeAudio = std::array::from_fn(|channel| {
+            let audio = &self.tape_model.tapes[id].audio[channel];
+            (0..length).map(|i| audio[i % audio.len()]).collect()
+        });
+        self.tape_model.tapes[id].audio = resized;
+        self.update_tape(id);
+        self.set_active_take(id, None);
+
+        let (multiple, divisor) = if length >= self.tape_length {
+            (length / self.tape_length, 1)
+        } else {
+            (1, self.tape_length / length)
+        };
+        self.log(LogMessage::Resized {
+            id,
+            multiple,
+            divisor,
+        });
+    }
+
+    /// Keeps what the tape has now as a new take
+    fn add_take(&mut self, id: usize) -> usize {
+        let (number, dropped) = self.takes.add(id, self.tape_model.tapes[id].audio.clone());
+        for (id, number) in dropped {
+            self.log(LogMessage::TakeDropped { id, number });
+            self.send_takes(id);
+        }
+        self.send_takes(id);
+
+        number
+    }
+
+    fn set_active_take(&mut self, id: usize, number: Option<usize>) {
+        self.takes.set_active(id, number);
+        self.send_takes(id);
+    }
+
+    fn send_takes(&mut self, id: usize) {
+        let _ = self.message_producer.try_push(ModulMessage::Takes {
+            id,
+            numbers: self.takes.numbers(id),
+            active: self.takes.active(id),
+        });
+    }
+
+    fn send_tape_lengths(&mut self) {
+        let tape_lengths: [usize; TAPE_COUNT] =
+            std::array::from_fn(|id| self.tape_model.tapes[id].length());
+        let _ = self
+            .message_producer
+            .try_push(ModulMessage::TapeLengths(tape_lengths));
+    }
+
+    /// A file that can't be written is logged, the buffer still goes back to the audio thread
+    fn write(&mut self, buffer: &[f32], name: &'static str) -> bool {
+        let Err(error) = write(buffer, name, self.sample_rate) else {
+            return true;
+        };
+        let error = match error {
+            hound::Error::IoError(error) => error.kind(),
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        self.log(LogMessage::WriteFailed { name, error });
+
+        false
+    }
+
+    fn log(&mut self, message: LogMessage) {
+        let _ = self.message_producer.try_push(ModulMessage::Log(message));
+    }
+
+    /// Blocks until the audio thread has room, buffers can't be dropped on the floor
+    fn finish(&mut self, mut done: Done) {
+        while let Err(rejected) = self.done_producer.try_push(done) {
+            done = rejected;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn update_waveform(&mut self, id: usize) {
+        let [left, right] = &self.tape_model.tapes[id].audio;
+        // Adding 1 to make sure that we have samples more than SAMPLE_GRAPH_SIZE
+        // Be my guest if you find a smarter way to do that
+        let size = (left.len() / (SAMPLE_GRAPH_SIZE + 1)).max(1);
+        for (point, (left, right)) in self.samples_for_graphs[id]
+            .iter_mut()
+            .zip(left.chunks(size).zip(right.chunks(size)))
+        {
+            *point = left.iter().chain(right).map(|sample| sample.abs()).sum();
+        }
+        let _ = self
+            .message_producer
+            .try_push(ModulMessage::SamplesForGraphs(self.samples_for_graphs));
+    }
+}
+
+/// Fits the longest multiplied tape
+fn recording_tape(tape_length: usize) -> TapeAudio {
+    std::array::from_fn(|_| vec![0.0; tape_length * MAX_LOOP_MULTIPLE])
+}
+
+/// Auto-advance records the next tape that is silent
+fn is_silent(audio: &TapeAudio) -> bool {
+    audio.iter().flatten().all(|sample| *sample == 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::{Section, SectionContent};
+    use ringbuf::HeapRb;
+
+    const TAPE_LENGTH: usize = 400;
+
+    fn worker() -> Worker {
+        let (_, job_consumer) = HeapRb::<Job>::new(JOB_QUEUE_SIZE).split();
+        let (done_producer, _) = HeapRb::<Done>::new(2_usize.pow(6)).split();
+        let (message_producer, _) = HeapRb::<ModulMessage>::new(2_usize.pow(6)).split();
+        Worker::new(
+            TAPE_LENGTH,
+            8000,
+            CaptureBuffer::new(TAPE_CHANNELS, TAPE_LENGTH),
+            job_consumer,
+            done_producer,
+            message_producer,
+        )
+    }
+
+    #[test]
+    fn scene_sections_are_automated() {
+        let mut worker = worker();
+        worker.tape_model.tapes[0].audio = std::array::from_fn(|_| vec![0.5; TAPE_LENGTH]);
+        let mut song = Song {
+            bar_length: TAPE_LENGTH,
+            loop_length: TAPE_LENGTH,
+            ..Song::default()
+        };
+        song.arrangement.push(Section {
+            bar_count: 1,
+            content: SectionContent::Scene(0),
+        });
+        // The scene pans the tape all the way left, the tapes are in the center
+        let mut scene = [TapeMix::default(); TAPE_COUNT];
+        scene[0].pan = -1.0;
+        song.scenes[0] = Some(scene);
+        song.automation_lanes[0].write(0, TAPE_LENGTH, 0.25);
+        song.automation_modes[0] = AutomationMode::Read;
+
+        let rendered = worker.render(&song, None);
+        assert_eq!(rendered.len(), TAPE_LENGTH * TAPE_CHANNELS);
+        for frame in rendered.chunks(TAPE_CHANNELS) {
+            assert!((frame[0] - 0.125).abs() < 1e-6);
+            assert!(frame[1].abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn defining_the_loop_keeps_the_other_tapes() {
+        let mut worker = worker();
+        // Twice as long as the loop and half as long
+        let ramp: Vec<f32> = (0..TAPE_LENGTH * 2).map(|i| i as f32).collect();
+        worker.tape_model.tapes[1].audio = std::array::from_fn(|_| ramp.clone());
+        worker.tape_model.tapes[2].audio =
+            std::array::from_fn(|_| ramp[..TAPE_LENGTH / 2].to_vec());
+
+        let tape_length = 600;
+        worker.handle(Job::DefineLoop {
+            id: 0,
+            audio: std::array::from_fn(|_| vec![0.5; tape_length + 3]),
+            tape_length,
+        });
+        let tapes = &worker.tape_model.tapes;
+        for channel in 0..TAPE_CHANNELS {
+            assert_eq!(tapes[0].audio[channel], vec![0.5; tape_length]);
+            // The tapes start on the first bar, they are cut or repeated from there
+            let doubled: Vec<f32> = (0..tape_length * 2)
+                .map(|i| (i % (TAPE_LENGTH * 2)) as f32)
+                .collect();
+            assert_eq!(tapes[1].audio[channel], doubled);
+            let halved: Vec<f32> = (0..tape_length / 2)
+                .map(|i| (i % (TAPE_LENGTH / 2)) as f32)
+                .collect();
+            assert_eq!(tapes[2].audio[channel], halved);
+            assert_eq!(tapes[3].audio[channel], vec![0.0; tape_length]);
+        }
+    }
+
+    #[test]
+    fn a_selected_take_replaces_the_tape() {
+        let mut worker = worker();
+        for sample in [1.0, 2.0] {
+            worker.tape_model.tapes[0].audio = std::array::from_fn(|_| vec![sample; TAPE_LENGTH]);
+            worker.add_take(0);
+        }
+        worker.handle(Job::SelectTake { id: 0, number: 1 });
+        assert_eq!(worker.tape_model.tapes[0].audio[0], vec![1.0; TAPE_LENGTH]);
+        assert_eq!(worker.takes.active(0), Some(1));
+    }
+
+    #[test]
+    fn a_comp_is_flattened_bar_by_bar() {
+        let mut worker = worker();
+        for sample in [1.0, 2.0] {
+            worker.tape_model.tapes[0].audio = std::array::from_fn(|_| vec![sample; TAPE_LENGTH]);
+            worker.add_take(0);
+        }
+        worker.tape_model.tapes[0].audio = std::array::from_fn(|_| vec![0.0; TAPE_LENGTH]);
+
+        // The first and the third bar come from the takes, the second one stays silent
+        let bar_length = TAPE_LENGTH / 4;
+        let fade_length = 10;
+        worker.handle(Job::Flatten {
+            id: 0,
+            comp: vec![Some(2), None, Some(1)],
+            bar_length,
+            fade_length,
+        });
+        let tape = &worker.tape_model.tapes[0].audio[0];
+        assert_eq!(tape[bar_length / 2], 2.0);
+        assert_eq!(tape[bar_length + bar_length / 2], 0.0);
+        assert_eq!(tape[bar_length * 2 + bar_length / 2], 1.0);
+        assert!(tape[bar_length * 3..].iter().all(|sample| *sample == 0.0));
+        // The bars fade in and out, there are no steps between them
+        for pair in tape.windows(2) {
+            assert!((pair[1] - pair[0]).abs() <= 2.0 / fade_length as f32 + 1e-6);
+        }
+
+        // The flattened tape is the newest take
+        assert_eq!(worker.takes.active(0), Some(3));
+        assert_eq!(
+            worker.takes.get(0, 3),
+            Some(&worker.tape_model.tapes[0].audio)
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use super::TapeMix;
+use crate::core::{mixer, TAPE_CHANNELS, TAPE_COUNT};
+
+pub const MAX_SECTIONS: usize = 32;
+
+/// What a section plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionContent {
+    /// Only these tapes play, with the mix they have right now
+    Tapes([bool; TAPE_COUNT]),
+    /// Every tape plays with the mix of a stored scene, automation and modulation move it
+    Scene(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Section {
+    pub bar_count: usize,
+    pub content: SectionContent,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Self {
+            bar_count: 4,
+            content: SectionContent::Tapes([true; TAPE_COUNT]),
+        }
+    }
+}
+
+/// Sections play one after the other from the first bar, the song ends after the last one
+/// Fixed size, so it can be handed to the audio thread without allocating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arrangement {
+    sections: [Section; MAX_SECTIONS],
+    section_count: usize,
+}
+
+impl Default for Arrangement {
+    fn default() -> Self {
+        Self {
+            sections: [Section::default(); MAX_SECTIONS],
+            section_count: 0,
+        }
+    }
+}
+
+impl Arrangement {
+    pub fn sections(&self) -> &[Section] {
+        &self.sections[..self.section_count]
+    }
+
+    pub fn sections_mut(&mut self) -> &mut [Section] {
+        &mut self.sections[..self.section_count]
+    }
+
+    /// Returns false if there is no room for another section
+    pub fn push(&mut self, section: Section) -> bool {
+        if self.section_count == MAX_SECTIONS {
+            return false;
+        }
+        self.sections[self.section_count] = section;
+        self.section_count += 1;
+        true
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.section_count {
+            self.sections[index..self.section_count].rotate_left(1);
+            self.section_count -= 1;
+        }
+    }
+
+    /// Length of the whole song
+    pub fn bar_count(&self) -> usize {
+        self.sections()
+            .iter()
+            .map(|section| section.bar_count)
+            .sum()
+    }
+
+    /// The index of the section that plays `bar` and the bar after it, bars are counted from 0
+    pub fn section_at(&self, bar: usize) -> Option<(usize, usize)> {
+        let mut end = 0;
+        for (index, section) in self.sections().iter().enumerate() {
+            end += section.bar_count;
+            if bar < end {
+                return Some((index, end));
+            }
+        }
+        None
+    }
+
+    /// The mix every tape starts from while the section plays, automation and modulation
+    /// move it like they move the tapes
+    /// `mixes` is what the tapes have now, a section with an empty scene plays them as they are
+    pub fn mixes(
+        &self,
+        index: usize,
+        mixes: [TapeMix; TAPE_COUNT],
+        scenes: &[Option<[TapeMix; TAPE_COUNT]>],
+    ) -> [TapeMix; TAPE_COUNT] {
+        match self.sections[index].content {
+            SectionContent::Tapes(_) => mixes,
+            SectionContent::Scene(scene) => scenes[scene].unwrap_or(mixes),
+        }
+    }
+
+    /// Volume and pan of every tape for each side while the section plays
+    /// `mixes` starts from the mixes of the section, tapes the section doesn't play are silent
+    pub fn gains(
+        &self,
+        index: usize,
+        mixes: &[TapeMix; TAPE_COUNT],
+    ) -> [[f32; TAPE_COUNT]; TAPE_CHANNELS] {
+        let is_active = match self.sections[index].content {
+            SectionContent::Tapes(is_active) => is_active,
+            SectionContent::Scene(_) => [true; TAPE_COUNT],
+        };
+        let volumes = std::array::from_fn(|id| mixes[id].get_volume());
+        let solos = std::array::from_fn(|id| mixes[id].is_solo);
+        let pans = std::array::from_fn(|id| mixes[id].pan);
+        let mut gains = mixer::mix_gains(&volumes, &solos, &pans);
+        // Solos are resolved first, a soloed tape that doesn't play still mutes the others
+        for side in gains.iter_mut() {
+            for (gain, is_active) in side.iter_mut().zip(is_active) {
+                if !is_active {
+                    *gain = 0.0;
+                }
+            }
+        }
+        gains
+    }
+}
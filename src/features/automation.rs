@@ -0,0 +1,138 @@
+use super::TapeMix;
+use crate::core::SAMPLE_GRAPH_SIZE;
+
+/// Points in one loop, the volume moves from point to point
+pub const AUTOMATION_POINTS: usize = 1024;
+/// Seconds without a change until a touched lane plays again
+pub const AUTOMATION_RELEASE_TIME: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AutomationMode {
+    /// The lane is ignored
+    #[default]
+    Off,
+    /// The lane plays, nothing is written
+    Read,
+    /// Written while the volume is being changed, the lane plays again once it is let go
+    Touch,
+    /// Written from the first change until the transport stops
+    Latch,
+    /// Written on every pass while the transport plays
+    Overwrite,
+}
+
+pub const AUTOMATION_MODES: [AutomationMode; 5] = [
+    AutomationMode::Off,
+    AutomationMode::Read,
+    AutomationMode::Touch,
+    AutomationMode::Latch,
+    AutomationMode::Overwrite,
+];
+
+impl std::fmt::Display for AutomationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AutomationMode::Off => write!(f, "off"),
+            AutomationMode::Read => write!(f, "read"),
+            AutomationMode::Touch => write!(f, "touch"),
+            AutomationMode::Latch => write!(f, "latch"),
+            AutomationMode::Overwrite => write!(f, "overwrite"),
+        }
+    }
+}
+
+/// Volume of a tape over one loop, it plays again on every pass
+/// Mute is written as a volume of 0, multiplied tapes play the lane once per loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomationLane {
+    points: [f32; AUTOMATION_POINTS],
+    is_empty: bool,
+}
+
+impl Default for AutomationLane {
+    fn default() -> Self {
+        Self {
+            points: [0.0; AUTOMATION_POINTS],
+            is_empty: true,
+        }
+    }
+}
+
+impl AutomationLane {
+    pub fn clear(&mut self) {
+        self.is_empty = true;
+    }
+
+    /// The point that plays at `index` in a loop of `loop_length` frames
+    fn point(index: usize, loop_length: usize) -> usize {
+        index * AUTOMATION_POINTS / loop_length
+    }
+
+    /// Frames from `index` to the start of the next point, at least 1
+    pub fn frames_to_next_point(index: usize, loop_length: usize) -> usize {
+        let next = Self::point(index, loop_length) + 1;
+        ((next * loop_length).div_ceil(AUTOMATION_POINTS))
+            .saturating_sub(index)
+            .max(1)
+    }
+
+    pub fn get(&self, index: usize, loop_length: usize) -> Option<f32> {
+        (!self.is_empty).then(|| self.points[Self::point(index, loop_length)])
+    }
+
+    /// The lane decides the volume of the tape, a muted tape stays muted
+    pub fn read(&self, mix: TapeMix, index: usize, loop_length: usize) -> TapeMix {
+        match self.get(index, loop_length) {
+            Some(volume) => TapeMix { volume, ..mix },
+            None => mix,
+        }
+    }
+
+    /// The first write fills the whole lane, so the rest of the loop keeps this volume
+    pub fn write(&mut self, index: usize, loop_length: usize, volume: f32) {
+        if self.is_empty {
+            self.points.fill(volume);
+            self.is_empty = false;
+        }
+        self.points[Self::point(index, loop_length)] = volume;
+    }
+
+    /// Every point of the graph is a point of the lane, None if there is nothing to draw
+    pub fn graph(&self) -> Option<[f32; SAMPLE_GRAPH_SIZE]> {
+        (!self.is_empty).then(|| {
+            std::array::from_fn(|i| self.points[i * AUTOMATION_POINTS / SAMPLE_GRAPH_SIZE])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_drives_the_volume() {
+        let mut lane = AutomationLane::default();
+        lane.write(0, 100, 0.25);
+        let mix = TapeMix {
+            volume: 0.8,
+            pan: 0.5,
+            is_muted: true,
+            is_solo: true,
+        };
+        let read = lane.read(mix, 50, 100);
+        assert_eq!(
+            read,
+            TapeMix {
+                volume: 0.25,
+                ..mix
+            }
+        );
+        assert_eq!(read.get_volume(), 0.0);
+    }
+
+    #[test]
+    fn empty_lanes_keep_the_mix() {
+        let mix = TapeMix::default();
+        assert_eq!(AutomationLane::default().read(mix, 0, 100), mix);
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Always-on recording of the input that is shared between the audio thread and the worker
+/// Samples are stored as bits in atomics, so the worker can copy captured bars
+/// while the audio thread keeps writing, without locks
+/// Every input channel is kept, one after the other, so any routing can be captured
+#[derive(Clone)]
+pub struct CaptureBuffer {
+    channel_count: usize,
+    samples: Arc<[AtomicU32]>,
+}
+
+impl CaptureBuffer {
+    /// `capacity` is in frames
+    pub fn new(channel_count: usize, capacity: usize) -> Self {
+        Self {
+            channel_count,
+            samples: (0..capacity * channel_count)
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.samples.len() / self.channel_count
+    }
+
+    /// Writes `samples` of `channel` starting at `index`, wrapping around at the end
+    pub fn write(&self, channel: usize, index: usize, samples: &[f32]) {
+        let capacity = self.capacity();
+        for (i, sample) in samples.iter().enumerate() {
+            self.samples[channel * capacity + (index + i) % capacity]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn read(&self, channel: usize, index: usize) -> f32 {
+        let capacity = self.capacity();
+        f32::from_bits(self.samples[channel * capacity + index % capacity].load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,44 @@
+/// Keeps the most recent samples, older ones are overwritten
+/// The buffer is allocated once, pushing never allocates
+pub struct CircularBuffer {
+    buffer: Vec<f32>,
+    position: usize,
+    filled: usize,
+}
+
+impl CircularBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: vec![0.0; capacity],
+            position: 0,
+            filled: 0,
+        }
+    }
+
+    /// Samples that can be read back, at most the capacity
+    pub fn available(&self) -> usize {
+        self.filled
+    }
+
+    pub fn clear(&mut self) {
+        self.position = 0;
+        self.filled = 0;
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        let capacity = self.buffer.len();
+        // Only the tail fits if there are more samples than the capacity
+        let samples = &samples[samples.len().saturating_sub(capacity)..];
+        let first = samples.len().min(capacity - self.position);
+        self.buffer[self.position..self.position + first].copy_from_slice(&samples[..first]);
+        self.buffer[..samples.len() - first].copy_from_slice(&samples[first..]);
+        self.position = (self.position + samples.len()) % capacity;
+        self.filled = (self.filled + samples.len()).min(capacity);
+    }
+
+    /// The sample that was pushed `age` samples ago, 1 is the latest one
+    pub fn get(&self, age: usize) -> f32 {
+        let capacity = self.buffer.len();
+        self.buffer[(self.position + capacity - age) % capacity]
+    }
+}
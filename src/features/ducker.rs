@@ -0,0 +1,88 @@
+use crate::core::{db_to_gain, gain_to_db, TAPE_CHANNELS};
+
+/// A tape or a bus is turned down while the key tape is over the threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuckerSettings {
+    /// The tape that is listened to, before its volume, None turns the ducker off
+    pub key: Option<usize>,
+    pub threshold: f32, // dB
+    pub ratio: f32,
+    pub attack: f32,  // ms
+    pub release: f32, // ms
+}
+
+impl Default for DuckerSettings {
+    fn default() -> Self {
+        Self {
+            key: None,
+            threshold: -30.0,
+            ratio: 4.0,
+            attack: 5.0,
+            release: 200.0,
+        }
+    }
+}
+
+/// Compressor with an external key, the gain moves smoothly in dB
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ducker {
+    settings: DuckerSettings,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    /// dB the signal is turned down by right now
+    reduction: f32,
+}
+
+impl Ducker {
+    pub fn set_settings(&mut self, settings: DuckerSettings, sample_rate: u32) {
+        self.settings = settings;
+        let time_coefficient =
+            |milliseconds: f32| (-1.0 / (milliseconds * 0.001 * sample_rate as f32)).exp();
+        self.attack_coefficient = time_coefficient(settings.attack);
+        self.release_coefficient = time_coefficient(settings.release);
+        if settings.key.is_none() {
+            self.reduction = 0.0;
+        }
+    }
+
+    pub fn settings(&self) -> DuckerSettings {
+        self.settings
+    }
+
+    pub fn key(&self) -> Option<usize> {
+        self.settings.key
+    }
+
+    /// Ducks `audio` frame by frame with the level of `key`, returns the largest reduction in dB
+    pub fn process(
+        &mut self,
+        key: [&[f32]; TAPE_CHANNELS],
+        audio: [&mut [f32]; TAPE_CHANNELS],
+    ) -> f32 {
+        let [key_left, key_right] = key;
+        let [left, right] = audio;
+        let slope = 1.0 - 1.0 / self.settings.ratio.max(1.0);
+        let mut largest: f32 = 0.0;
+        for (((key_left, key_right), left), right) in key_left
+            .iter()
+            .zip(key_right)
+            .zip(left.iter_mut())
+            .zip(right.iter_mut())
+        {
+            let level = gain_to_db(key_left.abs().max(key_right.abs()));
+            let target = (level - self.settings.threshold).max(0.0) * slope;
+            let coefficient = if target > self.reduction {
+                self.attack_coefficient
+            } else {
+                self.release_coefficient
+            };
+            self.reduction = target + coefficient * (self.reduction - target);
+            let gain = db_to_gain(-self.reduction);
+            *left *= gain;
+            *right *= gain;
+            largest = largest.max(self.reduction);
+        }
+
+        largest
+    }
+}
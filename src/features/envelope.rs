@@ -0,0 +1,94 @@
+/// Levels below this are silent, a release ends here
+const SILENCE: f32 = 0.0001; // -80 dB
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EnvStage {
+    #[default]
+    Off,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// How far the envelope moves in every frame, worked out once from the times in ms
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvelopeRates {
+    /// Added to the level, the attack is linear
+    pub attack: f32,
+    /// Decay and release fall exponentially, these are the coefficients
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl EnvelopeRates {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32, sample_rate: u32) -> Self {
+        let frames = |milliseconds: f32| (milliseconds * 0.001 * sample_rate as f32).max(1.0);
+        Self {
+            attack: 1.0 / frames(attack),
+            decay: (-1.0 / frames(decay)).exp(),
+            sustain: sustain.clamp(0.0, 1.0),
+            release: (-1.0 / frames(release)).exp(),
+        }
+    }
+}
+
+/// Attack, decay, sustain and release of a voice
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Envelope {
+    stage: EnvStage,
+    level: f32,
+}
+
+impl Envelope {
+    /// Starts from the level it is at, so a voice that is taken over doesn't click
+    pub fn start(&mut self) {
+        self.stage = EnvStage::Attack;
+    }
+
+    pub fn release(&mut self) {
+        if self.stage != EnvStage::Off {
+            self.stage = EnvStage::Release;
+        }
+    }
+
+    pub fn stage(&self) -> EnvStage {
+        self.stage
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != EnvStage::Off
+    }
+
+    /// The level of the next frame, from 0 to 1
+    pub fn next(&mut self, rates: &EnvelopeRates) -> f32 {
+        match self.stage {
+            EnvStage::Off => self.level = 0.0,
+            EnvStage::Attack => {
+                self.level += rates.attack;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = EnvStage::Decay;
+                }
+            }
+            EnvStage::Decay => {
+                self.level = rates.sustain + (self.level - rates.sustain) * rates.decay;
+                if self.level - rates.sustain < SILENCE {
+                    self.level = rates.sustain;
+                    self.stage = EnvStage::Sustain;
+                }
+            }
+            EnvStage::Sustain => self.level = rates.sustain,
+            EnvStage::Release => {
+                self.level *= rates.release;
+                if self.level < SILENCE {
+                    self.level = 0.0;
+                    self.stage = EnvStage::Off;
+                }
+            }
+        }
+
+        self.level
+    }
+}
@@ -0,0 +1,131 @@
+use crate::core::db_to_gain;
+
+/// The UI keeps a copy of these and sends it over whenever something changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputSettings {
+    pub gain: f32, // dB
+    pub is_polarity_inverted: bool,
+    pub is_high_pass_on: bool,
+    pub high_pass_frequency: f32, // Hz
+    pub is_gate_on: bool,
+    pub gate_threshold: f32, // dB
+    pub gate_attack: f32,    // ms
+    pub gate_release: f32,   // ms
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            gain: 0.0,
+            is_polarity_inverted: false,
+            is_high_pass_on: false,
+            high_pass_frequency: 80.0,
+            is_gate_on: false,
+            gate_threshold: -50.0,
+            gate_attack: 1.0,
+            gate_release: 100.0,
+        }
+    }
+}
+
+/// Trim, polarity, high-pass filter and noise gate, applied to the input
+/// before it is recorded or played through
+pub struct InputStrip {
+    settings: InputSettings,
+    sample_rate: f32,
+    gain: f32,
+    high_pass_coefficient: f32,
+    previous_inputs: Vec<f32>,
+    previous_outputs: Vec<f32>,
+    gate_threshold: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    envelope: f32,
+    gate_gain: f32,
+    is_gate_open: bool,
+}
+
+impl InputStrip {
+    pub fn new(sample_rate: u32, channel_count: usize) -> Self {
+        let mut input_strip = Self {
+            settings: InputSettings::default(),
+            sample_rate: sample_rate as f32,
+            gain: 1.0,
+            high_pass_coefficient: 1.0,
+            previous_inputs: vec![0.0; channel_count],
+            previous_outputs: vec![0.0; channel_count],
+            gate_threshold: 0.0,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+            envelope: 0.0,
+            gate_gain: 1.0,
+            is_gate_open: true,
+        };
+        input_strip.set_settings(InputSettings::default());
+
+        input_strip
+    }
+
+    pub fn set_settings(&mut self, settings: InputSettings) {
+        self.settings = settings;
+        self.gain = db_to_gain(settings.gain);
+        if settings.is_polarity_inverted {
+            self.gain = -self.gain;
+        }
+        // One pole high-pass, also removes any DC offset
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * settings.high_pass_frequency);
+        let dt = 1.0 / self.sample_rate;
+        self.high_pass_coefficient = rc / (rc + dt);
+        self.gate_threshold = db_to_gain(settings.gate_threshold);
+        self.attack_coefficient = self.time_coefficient(settings.gate_attack);
+        self.release_coefficient = self.time_coefficient(settings.gate_release);
+    }
+
+    pub fn is_gate_open(&self) -> bool {
+        !self.settings.is_gate_on || self.is_gate_open
+    }
+
+    /// Processes interleaved frames in place and returns the peak of the result
+    pub fn process(&mut self, block: &mut [f32], channel_count: usize) -> f32 {
+        let mut peak: f32 = 0.0;
+        for frame in block.chunks_exact_mut(channel_count) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let mut input = *sample * self.gain;
+                if self.settings.is_high_pass_on {
+                    let output = self.high_pass_coefficient
+                        * (self.previous_outputs[channel] + input - self.previous_inputs[channel]);
+                    self.previous_inputs[channel] = input;
+                    self.previous_outputs[channel] = output;
+                    input = output;
+                }
+                *sample = input;
+            }
+
+            if self.settings.is_gate_on {
+                // Channels are gated together so the stereo image doesn't move
+                let level = frame.iter().fold(0.0, |level: f32, s| level.max(s.abs()));
+                self.envelope = level.max(self.envelope * self.release_coefficient);
+                self.is_gate_open = self.envelope > self.gate_threshold;
+                let (target, coefficient) = if self.is_gate_open {
+                    (1.0, self.attack_coefficient)
+                } else {
+                    (0.0, self.release_coefficient)
+                };
+                self.gate_gain = target + coefficient * (self.gate_gain - target);
+                for sample in frame.iter_mut() {
+                    *sample *= self.gate_gain;
+                }
+            }
+
+            for sample in frame.iter() {
+                peak = peak.max(sample.abs());
+            }
+        }
+
+        peak
+    }
+
+    fn time_coefficient(&self, milliseconds: f32) -> f32 {
+        (-1.0 / (milliseconds * 0.001 * self.sample_rate)).exp()
+    }
+}
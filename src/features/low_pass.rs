@@ -0,0 +1,37 @@
+use crate::core::TAPE_CHANNELS;
+
+/// Two pole state variable low-pass, it stays stable while the cutoff moves
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LowPass {
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    states: [[f32; 2]; TAPE_CHANNELS],
+}
+
+impl LowPass {
+    pub fn set_cutoff(&mut self, frequency: f32, sample_rate: u32) {
+        let frequency = frequency.min(sample_rate as f32 * 0.45);
+        let g = (std::f32::consts::PI * frequency / sample_rate as f32).tan();
+        let k = std::f32::consts::SQRT_2; // Q of 0.707, no resonance
+        self.a1 = 1.0 / (1.0 + g * (g + k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn reset(&mut self) {
+        self.states = [[0.0; 2]; TAPE_CHANNELS];
+    }
+
+    pub fn process(&mut self, channel: usize, samples: &mut [f32]) {
+        let [ic1, ic2] = &mut self.states[channel];
+        for sample in samples.iter_mut() {
+            let v3 = *sample - *ic2;
+            let v1 = self.a1 * *ic1 + self.a2 * v3;
+            let v2 = *ic2 + self.a2 * *ic1 + self.a3 * v3;
+            *ic1 = 2.0 * v1 - *ic1;
+            *ic2 = 2.0 * v2 - *ic2;
+            *sample = v2;
+        }
+    }
+}
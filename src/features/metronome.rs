@@ -1,32 +1,23 @@
+/// Follows the timeline, it doesn't count samples on its own
+#[derive(Default)]
 pub struct Metronome {
     pub is_running: bool,
-    beat_index: u32,
-    sample_count: u32,
-    tick_period: f32,
+    beat_index: u64,
     show_beat: bool,
 }
 
-impl Metronome {
-    pub fn new(bpm: u16, sample_rate: u32, channel_count: u32) -> Self {
-        let tick_period = (sample_rate * channel_count * 60) as f32 / bpm as f32;
-        Self {
-            is_running: false,
-            beat_index: 0,
-            sample_count: 0,
-            tick_period,
-            show_beat: false,
-        }
-    }
+/// The part of a beat the beat is shown and heard
+const BEAT_LENGTH: f64 = 0.2;
 
-    pub fn update(&mut self) {
-        self.sample_count += 1;
-
-        let remainder = self.sample_count % self.tick_period as u32;
-        self.show_beat = remainder > 0 && remainder < 10_000;
-        self.beat_index = self.sample_count / self.tick_period as u32;
+impl Metronome {
+    /// `beats` is the timeline position in beats
+    pub fn update(&mut self, beats: f64) {
+        self.beat_index = beats as u64;
+        let fraction = beats.fract();
+        self.show_beat = fraction > 0.0 && fraction < BEAT_LENGTH;
     }
 
-    pub fn get_beat_index(&self) -> u32 {
+    pub fn get_beat_index(&self) -> u64 {
         self.beat_index
     }
 
@@ -1,4 +1,28 @@
+mod arrangement;
+mod automation;
+mod capture_buffer;
+mod circular_buffer;
+mod ducker;
+mod envelope;
+mod input_strip;
+mod low_pass;
 mod metronome;
+mod modulation;
+mod punch;
+mod synth;
+mod takes;
 mod tape;
+pub use arrangement::*;
+pub use automation::*;
+pub use capture_buffer::*;
+pub use circular_buffer::*;
+pub use ducker::*;
+pub use envelope::*;
+pub use input_strip::*;
+pub use low_pass::*;
 pub use metronome::*;
+pub use modulation::*;
+pub use punch::*;
+pub use synth::*;
+pub use takes::*;
 pub use tape::*;
@@ -0,0 +1,251 @@
+use super::TapeMix;
+use crate::core::TAPE_COUNT;
+
+pub const LFO_COUNT: usize = 4;
+pub const MAX_MODULATION_ROUTES: usize = 16;
+/// Frames between two modulation updates while there are routes
+pub const MODULATION_INTERVAL: usize = 64;
+/// Seconds an envelope follower takes to fall by about two thirds
+pub const FOLLOWER_RELEASE_TIME: f32 = 0.1;
+/// Seconds the playback rate can move a tape ahead or behind, the tape stays in sync on average
+pub const MAX_RATE_OFFSET: f32 = 0.01;
+/// Cutoff range of the tape filter, modulation moves it on a logarithmic scale
+const MIN_CUTOFF: f32 = 20.0;
+const MAX_CUTOFF: f32 = 20000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    /// A new random value on every cycle
+    Random,
+}
+
+pub const LFO_SHAPES: [LfoShape; 4] = [
+    LfoShape::Sine,
+    LfoShape::Triangle,
+    LfoShape::Square,
+    LfoShape::Random,
+];
+
+impl std::fmt::Display for LfoShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LfoShape::Sine => write!(f, "sine"),
+            LfoShape::Triangle => write!(f, "triangle"),
+            LfoShape::Square => write!(f, "square"),
+            LfoShape::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Synced to the tempo, a cycle is `beats` beats long and starts on the first bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    pub beats: f64,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            beats: 4.0,
+        }
+    }
+}
+
+impl Lfo {
+    /// From -1 to 1, `beats` is the timeline position in beats
+    /// `seed` keeps the random values of the LFOs apart
+    pub fn value(&self, beats: f64, seed: u64) -> f32 {
+        let cycles = beats / self.beats;
+        let phase = cycles.fract() as f32;
+        match self.shape {
+            LfoShape::Sine => (phase * 2.0 * std::f32::consts::PI).sin(),
+            LfoShape::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            // Hashed from the cycle, so it repeats after a locate and needs no state
+            LfoShape::Random => {
+                let mut x = (cycles as u64).wrapping_add(seed << 32);
+                x = (x ^ (x >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+                x = (x ^ (x >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+                x ^= x >> 33;
+                (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModulationSource {
+    Lfo(usize),
+    /// Follows the level of a tape
+    Tape(usize),
+    /// Follows the level of the input
+    Input,
+}
+
+impl std::fmt::Display for ModulationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModulationSource::Lfo(index) => write!(f, "LFO {}", index + 1),
+            ModulationSource::Tape(id) => write!(f, "tape {} env", id + 1),
+            ModulationSource::Input => write!(f, "input env"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModulationTarget {
+    Volume,
+    Pan,
+    Cutoff,
+    Rate,
+}
+
+pub const MODULATION_TARGETS: [ModulationTarget; 4] = [
+    ModulationTarget::Volume,
+    ModulationTarget::Pan,
+    ModulationTarget::Cutoff,
+    ModulationTarget::Rate,
+];
+
+impl std::fmt::Display for ModulationTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModulationTarget::Volume => write!(f, "volume"),
+            ModulationTarget::Pan => write!(f, "pan"),
+            ModulationTarget::Cutoff => write!(f, "cutoff"),
+            ModulationTarget::Rate => write!(f, "rate"),
+        }
+    }
+}
+
+/// Adds `depth` times the source to a parameter of a tape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModulationRoute {
+    pub source: ModulationSource,
+    pub tape: usize,
+    pub target: ModulationTarget,
+    /// From -1 to 1
+    pub depth: f32,
+}
+
+impl Default for ModulationRoute {
+    fn default() -> Self {
+        Self {
+            source: ModulationSource::Lfo(0),
+            tape: 0,
+            target: ModulationTarget::Volume,
+            depth: 0.5,
+        }
+    }
+}
+
+/// How far every parameter of a tape is moved, parameters are scaled so that 1 covers their range
+/// Volume goes from 0 to 1 and pan from -1 to 1, cutoff starts fully open
+/// and rate moves the tape by up to MAX_RATE_OFFSET
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TapeModulation {
+    pub volume: f32,
+    pub pan: f32,
+    pub cutoff: f32,
+    pub rate: f32,
+}
+
+impl TapeModulation {
+    pub fn apply(&self, mix: TapeMix) -> TapeMix {
+        TapeMix {
+            volume: (mix.volume + self.volume).clamp(0.0, 1.0),
+            pan: (mix.pan + self.pan).clamp(-1.0, 1.0),
+            ..mix
+        }
+    }
+
+    /// None while the filter is fully open
+    pub fn cutoff_frequency(&self) -> Option<f32> {
+        let cutoff = (1.0 + self.cutoff).clamp(0.0, 1.0);
+        (cutoff < 1.0).then(|| MIN_CUTOFF * (MAX_CUTOFF / MIN_CUTOFF).powf(cutoff))
+    }
+
+    /// In frames, how far behind the timeline the tape is read
+    pub fn rate_offset(&self, sample_rate: u32) -> f64 {
+        (self.rate.clamp(-1.0, 1.0) * MAX_RATE_OFFSET * sample_rate as f32) as f64
+    }
+}
+
+/// The UI keeps a copy of these and sends it over whenever something changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModulationSettings {
+    pub lfos: [Lfo; LFO_COUNT],
+    routes: [ModulationRoute; MAX_MODULATION_ROUTES],
+    route_count: usize,
+}
+
+impl Default for ModulationSettings {
+    fn default() -> Self {
+        Self {
+            lfos: [Lfo::default(); LFO_COUNT],
+            routes: [ModulationRoute::default(); MAX_MODULATION_ROUTES],
+            route_count: 0,
+        }
+    }
+}
+
+impl ModulationSettings {
+    pub fn routes(&self) -> &[ModulationRoute] {
+        &self.routes[..self.route_count]
+    }
+
+    pub fn routes_mut(&mut self) -> &mut [ModulationRoute] {
+        &mut self.routes[..self.route_count]
+    }
+
+    /// Returns false if there is no room for another route
+    pub fn push(&mut self, route: ModulationRoute) -> bool {
+        if self.route_count == MAX_MODULATION_ROUTES {
+            return false;
+        }
+        self.routes[self.route_count] = route;
+        self.route_count += 1;
+        true
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.route_count {
+            self.routes[index..self.route_count].rotate_left(1);
+            self.route_count -= 1;
+        }
+    }
+
+    /// Sums every route, `envelopes` are the followers of the tapes and then the input, from 0 to 1
+    pub fn modulate(&self, beats: f64, envelopes: &[f32]) -> [TapeModulation; TAPE_COUNT] {
+        let mut modulations = [TapeModulation::default(); TAPE_COUNT];
+        for route in self.routes() {
+            let value = match route.source {
+                ModulationSource::Lfo(index) => self.lfos[index].value(beats, index as u64),
+                ModulationSource::Tape(id) => envelopes[id],
+                ModulationSource::Input => envelopes[TAPE_COUNT],
+            };
+            let modulation = &mut modulations[route.tape];
+            let parameter = match route.target {
+                ModulationTarget::Volume => &mut modulation.volume,
+                ModulationTarget::Pan => &mut modulation.pan,
+                ModulationTarget::Cutoff => &mut modulation.cutoff,
+                ModulationTarget::Rate => &mut modulation.rate,
+            };
+            *parameter += value * route.depth;
+        }
+
+        modulations
+    }
+}
@@ -0,0 +1,85 @@
+/// The part of a tape a recording is allowed to overwrite, in frames
+/// The recording fades in at `start` and out at `end`, so the punch points don't click
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Punch {
+    pub start: usize,
+    pub end: usize,
+    pub fade_length: usize,
+}
+
+impl Punch {
+    /// Writes the recording over the tape inside the region, the rest of the tape stays as it is
+    pub fn apply(&self, tape: &mut [f32], recording: &[f32]) {
+        let end = self.end.min(tape.len()).min(recording.len());
+        let start = self.start.min(end);
+        // Both fades fit in the region, even if it is shorter than two fades
+        let fade_length = self.fade_length.min((end - start) / 2).max(1);
+        for i in start..end {
+            let weight = ((i - start + 1).min(end - i) as f32 / fade_length as f32).min(1.0);
+            tape[i] = tape[i] * (1.0 - weight) + recording[i] * weight;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTH: usize = 100;
+
+    /// A silent tape punched with a recording of ones, the tape shows the weights
+    fn punched(punch: Punch) -> Vec<f32> {
+        let mut tape = vec![0.0; LENGTH];
+        punch.apply(&mut tape, &[1.0; LENGTH]);
+        tape
+    }
+
+    #[test]
+    fn samples_outside_the_region_are_untouched() {
+        let mut tape: Vec<f32> = (0..LENGTH).map(|i| i as f32).collect();
+        let punch = Punch {
+            start: 20,
+            end: 60,
+            fade_length: 5,
+        };
+        punch.apply(&mut tape, &[-1.0; LENGTH]);
+        for (i, sample) in tape.iter().enumerate() {
+            if !(20..60).contains(&i) {
+                assert_eq!(*sample, i as f32);
+            }
+        }
+        assert_eq!(tape[40], -1.0);
+    }
+
+    #[test]
+    fn the_crossfade_is_continuous_at_both_edges() {
+        let fade_length = 10;
+        let tape = punched(Punch {
+            start: 20,
+            end: 60,
+            fade_length,
+        });
+        // No step between two samples is bigger than one step of the fades
+        for pair in tape.windows(2) {
+            assert!((pair[1] - pair[0]).abs() <= 1.0 / fade_length as f32 + 1e-6);
+        }
+        assert_eq!(tape[19], 0.0);
+        assert_eq!(tape[29], 1.0);
+        assert_eq!(tape[50], 1.0);
+        assert_eq!(tape[60], 0.0);
+    }
+
+    #[test]
+    fn the_region_is_clamped_at_the_loop_end() {
+        let fade_length = 10;
+        let tape = punched(Punch {
+            start: 80,
+            end: LENGTH * 2,
+            fade_length,
+        });
+        assert_eq!(tape[79], 0.0);
+        assert_eq!(tape[90], 1.0);
+        // The fade out ends on the last sample of the loop
+        assert!((tape[LENGTH - 1] - 1.0 / fade_length as f32).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,241 @@
+use super::{EnvStage, Envelope, EnvelopeRates, LowPass};
+use crate::core::{db_to_gain, TapeAudio, TAPE_CHANNELS};
+
+pub const MAX_VOICES: usize = 8;
+/// A sample plays at its own pitch on middle C
+const SAMPLE_NOTE: u8 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    #[default]
+    Saw,
+    Square,
+}
+
+pub const WAVEFORMS: [Waveform; 4] = [
+    Waveform::Sine,
+    Waveform::Triangle,
+    Waveform::Saw,
+    Waveform::Square,
+];
+
+impl std::fmt::Display for Waveform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Waveform::Sine => write!(f, "sine"),
+            Waveform::Triangle => write!(f, "triangle"),
+            Waveform::Saw => write!(f, "saw"),
+            Waveform::Square => write!(f, "square"),
+        }
+    }
+}
+
+/// The UI keeps a copy of these and sends it over whenever something changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthSettings {
+    pub waveform: Waveform,
+    /// A tape that is played instead of the oscillator, from its start
+    pub sample: Option<usize>,
+    pub attack: f32,  // ms
+    pub decay: f32,   // ms
+    pub sustain: f32, // 0 to 1
+    pub release: f32, // ms
+    pub cutoff: f32,  // Hz
+    pub volume: f32,  // dB
+}
+
+impl Default for SynthSettings {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Saw,
+            sample: None,
+            attack: 5.0,
+            decay: 200.0,
+            sustain: 0.7,
+            release: 300.0,
+            cutoff: 4000.0,
+            volume: -12.0,
+        }
+    }
+}
+
+/// Frequency of a MIDI note number, A4 is note 69
+pub fn note_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Voice {
+    note: u8,
+    velocity: f32,
+    /// From 0 to 1, one cycle of the waveform
+    phase: f32,
+    /// Phase added in every frame
+    increment: f32,
+    /// Frame of the sample that is playing, it moves faster or slower than the sample rate
+    position: f64,
+    rate: f64,
+    envelope: Envelope,
+    /// When the note started, the oldest voice is taken over first
+    started: u64,
+}
+
+impl Voice {
+    fn sample(&mut self, waveform: Waveform) -> f32 {
+        let (t, dt) = (self.phase, self.increment);
+        let sample = match waveform {
+            Waveform::Sine => (t * 2.0 * std::f32::consts::PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Square => {
+                let square = if t < 0.5 { 1.0 } else { -1.0 };
+                square + poly_blep(t, dt) - poly_blep((t + 0.5).fract(), dt)
+            }
+        };
+        self.phase = (self.phase + self.increment).fract();
+
+        sample
+    }
+
+    /// Both sides of the sample mixed down, the voice stops at the end of the sample
+    fn read(&mut self, sample: &TapeAudio) -> f32 {
+        let [left, right] = sample;
+        let frame = self.position as usize;
+        if frame + 1 >= left.len() {
+            self.envelope = Envelope::default();
+            return 0.0;
+        }
+        let fraction = (self.position - frame as f64) as f32;
+        let current = (left[frame] + right[frame]) * 0.5;
+        let next = (left[frame + 1] + right[frame + 1]) * 0.5;
+        self.position += self.rate;
+
+        current + (next - current) * fraction
+    }
+}
+
+/// Rounds off the jumps of the saw and the square, so high notes don't alias
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Polyphonic synthesizer, its voices are summed and go through one low-pass filter
+/// It is played like an input, tapes that are routed to it record it
+pub struct Synth {
+    settings: SynthSettings,
+    sample_rate: u32,
+    rates: EnvelopeRates,
+    gain: f32,
+    voices: [Voice; MAX_VOICES],
+    low_pass: LowPass,
+    note_count: u64,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut synth = Self {
+            settings: SynthSettings::default(),
+            sample_rate,
+            rates: EnvelopeRates::default(),
+            gain: 1.0,
+            voices: [Voice::default(); MAX_VOICES],
+            low_pass: LowPass::default(),
+            note_count: 0,
+        };
+        synth.set_settings(SynthSettings::default());
+
+        synth
+    }
+
+    pub fn set_settings(&mut self, settings: SynthSettings) {
+        self.settings = settings;
+        self.rates = EnvelopeRates::new(
+            settings.attack,
+            settings.decay,
+            settings.sustain,
+            settings.release,
+            self.sample_rate,
+        );
+        self.gain = db_to_gain(settings.volume);
+        self.low_pass.set_cutoff(settings.cutoff, self.sample_rate);
+    }
+
+    pub fn sample(&self) -> Option<usize> {
+        self.settings.sample
+    }
+
+    /// A note that is already playing is started again, otherwise a free voice plays it
+    /// If every voice is busy, the oldest released voice and then the oldest voice is taken over
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        let index = self
+            .voices
+            .iter()
+            .position(|voice| voice.envelope.is_active() && voice.note == note)
+            .or_else(|| {
+                self.voices
+                    .iter()
+                    .position(|voice| !voice.envelope.is_active())
+            })
+            .unwrap_or_else(|| {
+                let (index, _) = self
+                    .voices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, voice)| {
+                        (voice.envelope.stage() != EnvStage::Release, voice.started)
+                    })
+                    .unwrap();
+                index
+            });
+        self.note_count += 1;
+        let voice = &mut self.voices[index];
+        voice.note = note;
+        voice.velocity = velocity.clamp(0.0, 1.0);
+        voice.increment = note_frequency(note) / self.sample_rate as f32;
+        voice.position = 0.0;
+        voice.rate = (note_frequency(note) / note_frequency(SAMPLE_NOTE)) as f64;
+        voice.started = self.note_count;
+        voice.envelope.start();
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.note == note) {
+            voice.envelope.release();
+        }
+    }
+
+    /// Overwrites `audio` with the next frames, the synth is mono so both sides are the same
+    /// `sample` is the audio of the sample tape, without it the oscillator plays
+    pub fn process(&mut self, sample: Option<&TapeAudio>, audio: [&mut [f32]; TAPE_CHANNELS]) {
+        let [left, right] = audio;
+        left.fill(0.0);
+        let waveform = self.settings.waveform;
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.envelope.is_active())
+        {
+            for output in left.iter_mut() {
+                let level = voice.envelope.next(&self.rates) * voice.velocity * self.gain;
+                let value = match sample {
+                    Some(sample) => voice.read(sample),
+                    None => voice.sample(waveform),
+                };
+                *output += value * level;
+            }
+        }
+        // The filter keeps ringing for a moment after the last voice stops
+        self.low_pass.process(0, left);
+        right.copy_from_slice(left);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::core::{TapeAudio, TAPE_COUNT};
+
+/// A recording pass on a tape, numbered from 1 for every tape
+pub struct Take {
+    pub number: usize,
+    pub audio: TapeAudio,
+    /// Takes of all tapes are counted together, the oldest one is dropped first
+    order: u64,
+}
+
+/// Every recording pass on every tape is kept, until the takes hold more than `max_samples`
+pub struct Takes {
+    takes: [Vec<Take>; TAPE_COUNT],
+    next_numbers: [usize; TAPE_COUNT],
+    active: [Option<usize>; TAPE_COUNT],
+    next_order: u64,
+    max_samples: usize,
+}
+
+impl Takes {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            takes: std::array::from_fn(|_| Vec::new()),
+            next_numbers: [1; TAPE_COUNT],
+            active: [None; TAPE_COUNT],
+            next_order: 0,
+            max_samples,
+        }
+    }
+
+    /// The new take becomes the active one, returns its number and the takes that were dropped
+    pub fn add(&mut self, id: usize, audio: TapeAudio) -> (usize, Vec<(usize, usize)>) {
+        let number = self.next_numbers[id];
+        self.next_numbers[id] += 1;
+        self.takes[id].push(Take {
+            number,
+            audio,
+            order: self.next_order,
+        });
+        self.next_order += 1;
+        self.active[id] = Some(number);
+
+        let mut dropped = vec![];
+        // The newest take is always kept, even if it is larger than the limit on its own
+        while self.samples() > self.max_samples && self.count() > 1 {
+            let oldest = (0..TAPE_COUNT)
+                .filter_map(|id| self.takes[id].first().map(|take| (id, take.order)))
+                .min_by_key(|(_, order)| *order);
+            if let Some((id, _)) = oldest {
+                let take = self.takes[id].remove(0);
+                if self.active[id] == Some(take.number) {
+                    self.active[id] = None;
+                }
+                dropped.push((id, take.number));
+            }
+        }
+
+        (number, dropped)
+    }
+
+    pub fn get(&self, id: usize, number: usize) -> Option<&TapeAudio> {
+        self.takes[id]
+            .iter()
+            .find(|take| take.number == number)
+            .map(|take| &take.audio)
+    }
+
+    pub fn numbers(&self, id: usize) -> Vec<usize> {
+        self.takes[id].iter().map(|take| take.number).collect()
+    }
+
+    pub fn active(&self, id: usize) -> Option<usize> {
+        self.active[id]
+    }
+
+    /// None when the tape was changed some other way, it doesn't match any take then
+    pub fn set_active(&mut self, id: usize, number: Option<usize>) {
+        self.active[id] = number;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.max_samples);
+    }
+
+    fn count(&self) -> usize {
+        self.takes.iter().map(|takes| takes.len()).sum()
+    }
+
+    fn samples(&self) -> usize {
+        self.takes
+            .iter()
+            .flatten()
+            .map(|take| {
+                take.audio
+                    .iter()
+                    .map(|channel| channel.len())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTH: usize = 10;
+
+    fn audio(sample: f32, length: usize) -> TapeAudio {
+        std::array::from_fn(|_| vec![sample; length])
+    }
+
+    #[test]
+    fn the_oldest_take_is_dropped_at_the_limit() {
+        // Room for three takes of both channels
+        let mut takes = Takes::new(LENGTH * 2 * 3);
+        assert_eq!(takes.add(0, audio(1.0, LENGTH)), (1, vec![]));
+        assert_eq!(takes.add(1, audio(1.0, LENGTH)), (1, vec![]));
+        assert_eq!(takes.add(0, audio(2.0, LENGTH)), (2, vec![]));
+        takes.set_active(0, Some(1));
+
+        // The oldest take of any tape goes first
+        assert_eq!(takes.add(1, audio(2.0, LENGTH)), (2, vec![(0, 1)]));
+        assert_eq!(takes.numbers(0), vec![2]);
+        assert_eq!(takes.numbers(1), vec![1, 2]);
+        assert_eq!(takes.active(0), None);
+        assert!(takes.get(0, 1).is_none());
+    }
+
+    #[test]
+    fn the_newest_take_is_kept_over_the_limit() {
+        let mut takes = Takes::new(LENGTH * 2);
+        takes.add(0, audio(1.0, LENGTH));
+        let (number, dropped) = takes.add(1, audio(2.0, LENGTH * 4));
+        assert_eq!(dropped, vec![(0, 1)]);
+        assert_eq!(takes.numbers(1), vec![number]);
+        assert_eq!(takes.active(1), Some(number));
+    }
+
+    #[test]
+    fn a_take_is_selected_by_its_number() {
+        let mut takes = Takes::new(LENGTH * 2 * 3);
+        takes.add(0, audio(1.0, LENGTH));
+        takes.add(0, audio(2.0, LENGTH));
+        assert_eq!(takes.active(0), Some(2));
+
+        takes.set_active(0, Some(1));
+        assert_eq!(takes.active(0), Some(1));
+        assert_eq!(takes.get(0, 1), Some(&audio(1.0, LENGTH)));
+        assert_eq!(takes.get(0, 2), Some(&audio(2.0, LENGTH)));
+        assert!(takes.get(1, 1).is_none());
+
+        // Clearing the takes starts the numbers over
+        takes.clear();
+        assert_eq!(takes.add(0, audio(3.0, LENGTH)).0, 1);
+    }
+}
@@ -1,29 +1,65 @@
+use crate::core::TAPE_CHANNELS;
 use std::ops::{Add, AddAssign};
 
+/// Pan changes this much with every step
+const PAN_STEP: f32 = 0.1;
+
+/// Everything a scene keeps of a tape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TapeMix {
+    pub volume: f32,
+    pub pan: f32,
+    pub is_muted: bool,
+    pub is_solo: bool,
+}
+
+impl TapeMix {
+    /// Muted tapes have no volume
+    pub fn get_volume(&self) -> f32 {
+        if self.is_muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+}
+
+impl Default for TapeMix {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pan: 0.0,
+            is_muted: false,
+            is_solo: false,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Tape<T> {
     volume: f32,
+    /// From -1 (left) to 1 (right)
+    pan: f32,
     is_muted: bool,
     is_solo: bool,
-    pub audio: Vec<T>,
+    /// Planar, one buffer per channel
+    pub audio: [Vec<T>; TAPE_CHANNELS],
 }
 
 impl<T: Copy + Clone + Add + AddAssign> Tape<T> {
     pub fn new(default: T, length: usize) -> Self {
         Self {
             volume: 1.0,
+            pan: 0.0,
             is_muted: false,
             is_solo: false,
-            audio: vec![default; length],
+            audio: std::array::from_fn(|_| vec![default; length]),
         }
     }
 
-    pub fn get_volume(&self) -> f32 {
-        if self.is_muted {
-            0.0
-        } else {
-            self.volume
-        }
+    /// In frames, every channel has the same length
+    pub fn length(&self) -> usize {
+        self.audio[0].len()
     }
 
     pub fn toggle_mute(&mut self) {
@@ -34,10 +70,6 @@ impl<T: Copy + Clone + Add + AddAssign> Tape<T> {
         self.is_solo = !self.is_solo;
     }
 
-    pub fn is_solo(&self) -> bool {
-        self.is_solo
-    }
-
     pub fn volume_up(&mut self) {
         if self.volume < 1.0 {
             self.volume += 0.01;
@@ -50,15 +82,42 @@ impl<T: Copy + Clone + Add + AddAssign> Tape<T> {
         }
     }
 
+    pub fn pan_left(&mut self) {
+        self.pan = (self.pan - PAN_STEP).max(-1.0);
+    }
+
+    pub fn pan_right(&mut self) {
+        self.pan = (self.pan + PAN_STEP).min(1.0);
+    }
+
+    pub fn get_mix(&self) -> TapeMix {
+        TapeMix {
+            volume: self.volume,
+            pan: self.pan,
+            is_muted: self.is_muted,
+            is_solo: self.is_solo,
+        }
+    }
+
+    pub fn set_mix(&mut self, mix: TapeMix) {
+        self.volume = mix.volume;
+        self.pan = mix.pan;
+        self.is_muted = mix.is_muted;
+        self.is_solo = mix.is_solo;
+    }
+
     pub fn clear(&mut self, default: T) {
-        for i in 0..self.audio.len() {
-            self.audio[i] = default;
+        for channel in self.audio.iter_mut() {
+            channel.fill(default);
         }
     }
 
-    pub fn add(&mut self, other: Vec<T>) {
-        for i in 0..self.audio.len() {
-            self.audio[i] += other[i];
+    /// A shorter tape is repeated, so multiplied and divided tapes can be merged
+    pub fn add(&mut self, other: [Vec<T>; TAPE_CHANNELS]) {
+        for (channel, other) in self.audio.iter_mut().zip(other) {
+            for i in 0..channel.len() {
+                channel[i] += other[i % other.len()];
+            }
         }
     }
 }
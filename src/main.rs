@@ -7,6 +7,11 @@ use colored::Colorize;
 mod winit_view;
 use winit_view::app;
 
+// Tests check the audio path in release builds too
+#[cfg(any(debug_assertions, test))]
+#[global_allocator]
+static ALLOCATOR: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     let config = Config::new(args);
@@ -17,6 +22,8 @@ fn main() {
 pub struct Config {
     pub bpm: u16,
     pub bar_count: usize,
+    /// The first recording is unquantized and defines the loop length and the BPM
+    pub is_free_running: bool,
 }
 
 impl Config {
@@ -25,6 +32,7 @@ impl Config {
         let default_bar_count = 4;
         let mut bpm: u16 = default_bpm;
         let mut bar_count: usize = default_bar_count;
+        let mut is_free_running = false;
 
         println!();
         println!("{}", "                 ".on_yellow());
@@ -41,19 +49,30 @@ impl Config {
                 let arg_one: &str = &args[1][..];
                 let arg_two: &str = &args[2][..];
 
-                bpm = arg_one.parse().unwrap();
                 bar_count = arg_two.parse().unwrap();
-
-                println!(
-                    "Modul will start with config {} BPM and {} bars...\n",
-                    bpm, bar_count
-                );
+                if arg_one == "free" {
+                    is_free_running = true;
+                    println!(
+                        "Modul will start free running, the first recording defines {} bars...\n",
+                        bar_count
+                    );
+                } else {
+                    bpm = arg_one.parse().unwrap();
+                    println!(
+                        "Modul will start with config {} BPM and {} bars...\n",
+                        bpm, bar_count
+                    );
+                }
             }
             Ordering::Greater => {
                 println!("Too many arguments...\nModul will start with default config {} BPM and {} bars...\n", default_bpm, default_bar_count)
             }
         }
 
-        Self { bpm, bar_count }
+        Self {
+            bpm,
+            bar_count,
+            is_free_running,
+        }
     }
 }
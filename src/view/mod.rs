@@ -1,3 +1,6 @@
+// Visualizations from before the wgpu view, they are kept to be ported and aren't drawn
+#![allow(dead_code)]
+
 mod camera;
 mod scene;
 mod scene2;
@@ -7,9 +10,6 @@ mod visualization;
 // mod windows;
 
 pub use camera::*;
-pub use scene::*;
-pub use scene2::*;
-pub use scene3::*;
 // pub use stage::*;
 pub use visualization::*;
 // pub use windows::*;
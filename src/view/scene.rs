@@ -5,7 +5,6 @@ use crate::view::visualization::object::Object;
 use crate::view::Camera;
 use glam::{EulerRot, Mat4, Quat, Vec3};
 use miniquad as mq;
-use rand;
 
 pub struct Scene {
     camera: Camera,
@@ -20,7 +19,6 @@ pub struct Scene {
 impl Scene {
     pub fn new(mq_ctx: &mut mq::Context) -> Self {
         // let mut quads = Vec::with_capacity(TAPE_COUNT);
-        let mut rng = rand::thread_rng();
 
         let camera = Camera::new(mq_ctx.screen_size(), 60.0);
 
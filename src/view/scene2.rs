@@ -2,7 +2,7 @@ use self::super::visualization::object::Object;
 use super::visualization::material;
 use crate::core::Modul;
 use crate::view::Camera;
-use glam::{vec3, EulerRot, Mat4, Quat, Vec3};
+use glam::{EulerRot, Mat4, Quat, Vec3};
 use miniquad as mq;
 
 pub struct Scene2 {
@@ -53,7 +53,7 @@ impl Scene2 {
         }
     }
 
-    pub fn update(&mut self, modul: &Modul, delta_time: f32) {
+    pub fn update(&mut self, _modul: &Modul, delta_time: f32) {
         self.rotation += 0.1 * delta_time;
 
         self.camera.update(delta_time, 0.0);
@@ -62,7 +62,7 @@ impl Scene2 {
         }
     }
 
-    pub fn draw(&mut self, ctx: &mut mq::Context, modul: &Modul) {
+    pub fn draw(&mut self, ctx: &mut mq::Context, _modul: &Modul) {
         let view_proj = self.camera.get_view_projection();
 
         for obj in &self.objects {
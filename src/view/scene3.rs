@@ -1,11 +1,9 @@
 use super::visualization::material;
 use crate::core::Modul;
-use crate::core::TAPE_COUNT;
 use crate::view::visualization::object::Object;
 use crate::view::Camera;
 use glam::{EulerRot, Mat4, Quat, Vec3};
 use miniquad as mq;
-use rand;
 
 pub struct Scene3 {
     camera: Camera,
@@ -17,8 +15,6 @@ pub struct Scene3 {
 
 impl Scene3 {
     pub fn new(mq_ctx: &mut mq::Context) -> Self {
-        let mut rng = rand::thread_rng();
-
         let camera = Camera::new(mq_ctx.screen_size(), 60.0);
 
         Self {
@@ -43,7 +39,7 @@ impl Scene3 {
         }
     }
 
-    pub fn update(&mut self, modul: &Modul, delta_time: f32) {
+    pub fn update(&mut self, _modul: &Modul, delta_time: f32) {
         // self.some_obj.update() would looks nicer
         self.rotation += 0.1 * delta_time;
 
@@ -5,7 +5,5 @@ pub mod quad;
 pub mod sphere;
 
 pub use cube::*;
-pub use material::*;
 pub use object::*;
-pub use quad::*;
 pub use sphere::*;
@@ -10,6 +10,7 @@ pub struct Object {
 }
 
 impl Object {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(ctx: &mut GraphicsContext, shader: &str) -> ObjectBuilder {
         ObjectBuilder::new(ctx, shader)
     }
@@ -55,12 +55,12 @@ impl Quad {
             super::material::text_shader_meta(),
         )
         .unwrap();
-        let color_blend = BlendState::new(
+        let _color_blend = BlendState::new(
             mq::Equation::Add,
             mq::BlendFactor::Value(BlendValue::SourceColor),
             mq::BlendFactor::OneMinusValue(BlendValue::SourceColor),
         );
-        let alpha_blend = BlendState::new(
+        let _alpha_blend = BlendState::new(
             mq::Equation::Add,
             mq::BlendFactor::Value(BlendValue::SourceAlpha),
             mq::BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
@@ -51,12 +51,12 @@ impl Sphere {
         };
 
         let shader = Shader::new(ctx, VERTEX, fragment, text_shader_meta()).unwrap();
-        let color_blend = BlendState::new(
+        let _color_blend = BlendState::new(
             mq::Equation::Add,
             mq::BlendFactor::Value(BlendValue::SourceColor),
             mq::BlendFactor::OneMinusValue(BlendValue::SourceColor),
         );
-        let alpha_blend = BlendState::new(
+        let _alpha_blend = BlendState::new(
             mq::Equation::Add,
             mq::BlendFactor::Value(BlendValue::SourceAlpha),
             mq::BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
@@ -86,6 +86,7 @@ impl Sphere {
     }
 }
 
+#[allow(clippy::explicit_counter_loop)]
 fn get_vertices_and_indices() -> ([Vertex; VERTEX_COUNT], [u16; VERTEX_COUNT * 6]) {
     let mut vertices = Vec::new();
 
@@ -13,9 +13,11 @@ use crate::{
     Config,
 };
 
+#[allow(dead_code)]
 pub struct AudioApp {}
 
 pub struct App {
+    #[allow(dead_code)]
     rolling_frame_time: VecDeque<f32>,
     config: Config,
 }
@@ -45,7 +47,7 @@ pub async fn start(config: Config) {
         .build(&event_loop)
         .unwrap();
 
-    let mut app = App::new(config).await;
+    let app = App::new(config).await;
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
         backends: wgpu::Backends::PRIMARY,
         ..Default::default()
@@ -110,11 +112,13 @@ pub async fn start(config: Config) {
     let init = [0.0; 60];
     let mut rolling_frame_times = VecDeque::from(init);
     let mut earlier = std::time::Instant::now();
-    let mut elapsed_time = 0.0;
+    let _elapsed_time = 0.0;
 
     let mut modul = Modul::new(&app.config);
 
-    let r = event_loop.run(move |event, elwt| match event {
+    // The configuration is replaced before it is read on every resize
+    #[allow(unused_assignments)]
+    let _r = event_loop.run(move |event, elwt| match event {
         Event::WindowEvent {
             window_id,
             event: WindowEvent::Resized(size),
@@ -145,7 +149,7 @@ pub async fn start(config: Config) {
             earlier = std::time::Instant::now();
             rolling_frame_times.pop_front();
             rolling_frame_times.push_back(frame_time.as_secs_f32());
-            let fps = calculate_fps(&rolling_frame_times);
+            let _fps = calculate_fps(&rolling_frame_times);
             let output_frame = match surface.get_current_texture() {
                 Ok(frame) => frame,
                 Err(wgpu::SurfaceError::Outdated) => {
@@ -185,5 +189,5 @@ pub fn calculate_fps(times: &VecDeque<f32>) -> f32 {
     let sum: f32 = times.iter().sum();
 
     let average_time = sum / times.len() as f32;
-    return 1.0 / average_time;
+    1.0 / average_time
 }
@@ -1,6 +1,5 @@
 use super::windows::Windows;
 use crate::core::Modul;
-use crate::winit_view::app::App;
 use egui::ViewportId;
 use egui_wgpu::wgpu::TextureFormat;
 use egui_wgpu::{Renderer, ScreenDescriptor};
@@ -12,14 +11,12 @@ use wgpu::{Device, Queue};
 use winit::window::Window;
 
 struct Test {
-    is_window_open: bool,
     windows: Windows,
 }
 
 impl Test {
     fn new(ctx: &Context) -> Self {
         Self {
-            is_window_open: false,
             windows: Windows::new(ctx),
         }
     }
@@ -37,8 +34,6 @@ pub struct Gui {
     view: Test,
     paint_jobs: Vec<ClippedPrimitive>,
     textures: TexturesDelta,
-    width: u32,
-    height: u32,
 }
 
 impl Gui {
@@ -55,7 +50,6 @@ impl Gui {
             Some(scale_factor as f32),
             Some(max_texture_size),
         );
-        let max_texture_size = device.limits().max_texture_dimension_2d as usize;
 
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [size.width, size.height],
@@ -74,8 +68,6 @@ impl Gui {
             view,
             paint_jobs: vec![],
             textures,
-            width: size.width,
-            height: size.height,
         }
     }
 
@@ -116,7 +108,7 @@ impl Gui {
         // Upload all resources to the GPU.
         for (id, image_delta) in &self.textures.set {
             self.renderer
-                .update_texture(&device, &queue, *id, image_delta);
+                .update_texture(device, queue, *id, image_delta);
         }
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -124,8 +116,8 @@ impl Gui {
         });
 
         self.renderer.update_buffers(
-            &device,
-            &queue,
+            device,
+            queue,
             &mut encoder,
             &self.paint_jobs,
             &self.screen_descriptor,
@@ -152,7 +144,7 @@ impl Gui {
                 .render(&mut rpass, &self.paint_jobs, &self.screen_descriptor);
         }
         // dropping rpass here
-        &queue.submit(Some(encoder.finish()));
+        queue.submit(Some(encoder.finish()));
         // Cleanup
         let textures = std::mem::take(&mut self.textures);
         for id in &textures.free {
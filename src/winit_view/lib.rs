@@ -7,6 +7,7 @@ pub struct VertexColor {
     color: [f32; 3],
 }
 
+#[allow(dead_code)]
 pub const VERTICES_COLOR: &[VertexColor] = &[
     VertexColor {
         position: [0.0, 0.5, 0.0],
@@ -1,16 +1,19 @@
+pub mod window_arrangement;
 pub mod window_controls;
+pub mod window_ducking;
+pub mod window_input;
 pub mod window_log;
 pub mod window_metronome;
+pub mod window_modulation;
+pub mod window_output;
+pub mod window_scenes;
 pub mod window_settings;
 pub mod window_stats;
+pub mod window_synth;
 pub mod window_tapes;
+pub mod window_transport;
+#[allow(clippy::module_inception)]
 pub mod windows;
 
 pub use crate::core::Modul;
-pub use window_controls::*;
-pub use window_log::*;
-pub use window_metronome::*;
-pub use window_settings::*;
-pub use window_stats::*;
-pub use window_tapes::*;
 pub use windows::*;
@@ -0,0 +1,113 @@
+use super::Drawable;
+use crate::core::{Modul, SCENE_COUNT, TAPE_COUNT};
+use crate::features::{Arrangement, Section, SectionContent, MAX_SECTIONS};
+use egui::*;
+
+/// Sections of the song, each plays some of the tapes or a scene for a number of bars
+#[derive(Default)]
+pub struct WindowArrangement {
+    arrangement: Arrangement,
+    is_song_mode: bool,
+    with_stems: bool,
+}
+
+impl Drawable for WindowArrangement {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        let arrangement = &mut self.arrangement;
+        egui::Window::new("arrangement").show(egui_ctx, |ui| {
+            let mut changed = false;
+            let mut removed = None;
+            let playing = modul.get_section();
+            Grid::new("sections").striped(true).show(ui, |ui| {
+                for (index, section) in arrangement.sections_mut().iter_mut().enumerate() {
+                    let number = RichText::new((index + 1).to_string());
+                    ui.label(if playing == Some(index) {
+                        number.strong()
+                    } else {
+                        number.weak()
+                    });
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut section.bar_count)
+                                .clamp_range(1..=256)
+                                .suffix(" bars"),
+                        )
+                        .changed();
+                    changed |= draw_section_content(ui, index, section);
+                    if ui.small_button("x").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = removed {
+                arrangement.remove(index);
+                changed = true;
+            }
+            ui.add_enabled_ui(arrangement.sections().len() < MAX_SECTIONS, |ui| {
+                if ui.button("add section").clicked() {
+                    changed |= arrangement.push(Section::default());
+                }
+            });
+            ui.label(format!("{} bars", arrangement.bar_count()));
+            if changed {
+                modul.set_arrangement(*arrangement);
+            }
+
+            ui.separator();
+            if ui
+                .checkbox(&mut self.is_song_mode, "song mode")
+                .on_hover_text("plays the arrangement from the first bar")
+                .changed()
+            {
+                modul.set_song_mode(self.is_song_mode);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("render").clicked() {
+                    modul.render_arrangement(self.with_stems);
+                }
+                ui.checkbox(&mut self.with_stems, "stems")
+                    .on_hover_text("every tape is also written to a file of its own");
+            });
+        });
+    }
+}
+
+/// Active tapes or a scene, returns true if the section changed
+fn draw_section_content(ui: &mut Ui, index: usize, section: &mut Section) -> bool {
+    let mut changed = false;
+    let mut content = section.content;
+    ui.horizontal(|ui| {
+        ComboBox::from_id_source(("section", index))
+            .width(70.0)
+            .selected_text(match content {
+                SectionContent::Tapes(_) => "tapes".to_string(),
+                SectionContent::Scene(scene) => format!("scene {}", scene + 1),
+            })
+            .show_ui(ui, |ui| {
+                let tapes = match section.content {
+                    SectionContent::Tapes(is_active) => is_active,
+                    SectionContent::Scene(_) => [true; TAPE_COUNT],
+                };
+                ui.selectable_value(&mut content, SectionContent::Tapes(tapes), "tapes");
+                for scene in 0..SCENE_COUNT {
+                    ui.selectable_value(
+                        &mut content,
+                        SectionContent::Scene(scene),
+                        format!("scene {}", scene + 1),
+                    );
+                }
+            });
+        if let SectionContent::Tapes(is_active) = &mut content {
+            for (id, is_active) in is_active.iter_mut().enumerate() {
+                ui.toggle_value(is_active, (id + 1).to_string());
+            }
+        }
+    });
+    if content != section.content {
+        section.content = content;
+        changed = true;
+    }
+
+    changed
+}
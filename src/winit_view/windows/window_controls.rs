@@ -11,15 +11,30 @@ impl Drawable for WindowControls {
             ui.label("select tape ([1-8])");
             ui.label("select secondary tape (shift+[1-8])");
             ui.label("toggle record tape (Space)");
+            ui.label("arm record tape, starts at threshold (R)");
+            ui.label("capture the last bars you played (G)");
             ui.label("clear tape (C)");
             ui.label("clear all tapes (shift + C)");
             ui.label("mute/unmute tape (M)");
             ui.label("solo/unsolo tape (S)");
             ui.label("merge tapes (N)");
+            ui.label("double tape length (X)");
+            ui.label("halve tape length (shift + X)");
             ui.label("toggle record live (T)");
             ui.label("toggle play-through (Y)");
             ui.label("write to disc (W)");
             ui.label("tape volume (up/down)");
+            ui.label("tape pan (left/right)");
+            ui.label("record enable tape (E)");
+            ui.label("recall scene (F1-F8)");
+            ui.label("store scene (shift + F1-F8)");
+            ui.label("play/pause (P)");
+            ui.label("stop, back to the first bar (shift + P)");
+            ui.label("return to zero (Home)");
+            ui.label("key mode, play the synth with the keyboard (`)");
+            ui.label("key mode notes (A-K, W E T Y U)");
+            ui.label("key mode octave down/up (Z/X)");
+            ui.label("key mode velocity down/up (C/V)");
             ui.label("quit (Esc)");
         });
     }
@@ -0,0 +1,89 @@
+use super::Drawable;
+use crate::core::{Modul, TAPE_COUNT};
+use egui::*;
+
+/// A ducker for every tape and the master bus, keyed by another tape
+#[derive(Default)]
+pub struct WindowDucking {}
+
+impl Drawable for WindowDucking {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        egui::Window::new("ducking").show(egui_ctx, |ui| {
+            egui::Grid::new("duckers").striped(true).show(ui, |ui| {
+                ui.label("");
+                ui.label("key");
+                ui.label("threshold (dB)");
+                ui.label("ratio");
+                ui.label("attack (ms)");
+                ui.label("release (ms)");
+                ui.label("reduction");
+                ui.end_row();
+                for target in 0..=TAPE_COUNT {
+                    draw_ducker(ui, modul, target);
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+fn target_label(target: usize) -> String {
+    if target == TAPE_COUNT {
+        "master".to_string()
+    } else {
+        format!("tape {}", target + 1)
+    }
+}
+
+fn draw_ducker(ui: &mut Ui, modul: &mut Modul, target: usize) {
+    let mut settings = modul.get_ducker(target);
+    let mut changed = false;
+    ui.label(target_label(target));
+    ComboBox::from_id_source(("key", target))
+        .width(60.0)
+        .selected_text(
+            settings
+                .key
+                .map_or("off".to_string(), |key| format!("tape {}", key + 1)),
+        )
+        .show_ui(ui, |ui| {
+            changed |= ui
+                .selectable_value(&mut settings.key, None, "off")
+                .changed();
+            // A tape can't duck itself
+            for key in (0..TAPE_COUNT).filter(|key| *key != target) {
+                changed |= ui
+                    .selectable_value(&mut settings.key, Some(key), format!("tape {}", key + 1))
+                    .changed();
+            }
+        });
+    ui.add_enabled_ui(settings.key.is_some(), |ui| {
+        changed |= ui
+            .add(DragValue::new(&mut settings.threshold).clamp_range(-60.0..=0.0))
+            .changed();
+    });
+    ui.add_enabled_ui(settings.key.is_some(), |ui| {
+        changed |= ui
+            .add(
+                DragValue::new(&mut settings.ratio)
+                    .clamp_range(1.0..=20.0)
+                    .speed(0.1)
+                    .suffix(":1"),
+            )
+            .changed();
+    });
+    ui.add_enabled_ui(settings.key.is_some(), |ui| {
+        changed |= ui
+            .add(DragValue::new(&mut settings.attack).clamp_range(0.1..=100.0))
+            .changed();
+    });
+    ui.add_enabled_ui(settings.key.is_some(), |ui| {
+        changed |= ui
+            .add(DragValue::new(&mut settings.release).clamp_range(10.0..=2000.0))
+            .changed();
+    });
+    super::window_input::draw_gain_reduction(ui, modul.get_gain_reduction(target), vec2(80.0, 8.0));
+    if changed {
+        modul.set_ducker(target, settings);
+    }
+}
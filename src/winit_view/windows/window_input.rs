@@ -0,0 +1,151 @@
+use super::Drawable;
+use crate::core::{gain_to_db, InputRoute, Modul, TAPE_COUNT};
+use crate::features::InputSettings;
+use egui::*;
+
+const METER_FLOOR: f32 = -60.0; // dB
+const GAIN_REDUCTION_RANGE: f32 = 24.0; // dB
+
+#[derive(Default)]
+pub struct WindowInput {
+    settings: InputSettings,
+}
+
+impl Drawable for WindowInput {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        let settings = &mut self.settings;
+        egui::Window::new("input").show(egui_ctx, |ui| {
+            let mut changed = false;
+            changed |= ui
+                .add(Slider::new(&mut settings.gain, -24.0..=24.0).text("trim (dB)"))
+                .changed();
+            changed |= ui
+                .checkbox(&mut settings.is_polarity_inverted, "invert polarity Ø")
+                .changed();
+            ui.separator();
+            changed |= ui
+                .checkbox(&mut settings.is_high_pass_on, "high-pass")
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.high_pass_frequency, 20.0..=300.0)
+                        .logarithmic(true)
+                        .text("cutoff (Hz)"),
+                )
+                .changed();
+            ui.separator();
+            changed |= ui
+                .checkbox(&mut settings.is_gate_on, "noise gate")
+                .changed();
+            changed |= ui
+                .add(Slider::new(&mut settings.gate_threshold, -80.0..=0.0).text("threshold (dB)"))
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.gate_attack, 0.1..=50.0)
+                        .logarithmic(true)
+                        .text("attack (ms)"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.gate_release, 5.0..=1000.0)
+                        .logarithmic(true)
+                        .text("release (ms)"),
+                )
+                .changed();
+
+            if changed {
+                modul.set_input_settings(*settings);
+            }
+
+            ui.separator();
+            draw_routing(ui, modul);
+        });
+    }
+}
+
+/// Input channels of every tape, one channel or a pair of channels, or the synth
+fn draw_routing(ui: &mut Ui, modul: &mut Modul) {
+    let channel_count = modul.stats.input_channel_count as usize;
+    let routes: Vec<InputRoute> = (0..channel_count)
+        .map(InputRoute::Mono)
+        .chain((0..channel_count.saturating_sub(1)).map(InputRoute::Stereo))
+        .chain(std::iter::once(InputRoute::Synth))
+        .collect();
+    ui.label("routing");
+    Grid::new("routing").show(ui, |ui| {
+        for id in 0..TAPE_COUNT {
+            let mut route = modul.get_input_route(id);
+            ui.label(format!("tape {}", id + 1));
+            ComboBox::from_id_source(("route", id))
+                .selected_text(route.to_string())
+                .show_ui(ui, |ui| {
+                    for option in &routes {
+                        ui.selectable_value(&mut route, *option, option.to_string());
+                    }
+                });
+            if route != modul.get_input_route(id) {
+                modul.set_input_route(id, route);
+            }
+            ui.end_row();
+        }
+    });
+}
+
+/// Small horizontal meter for the top panel, so the input level is always visible
+pub fn draw_input_meter(ui: &mut Ui, modul: &Modul) {
+    ui.label("in");
+    draw_meter(ui, modul.get_input_peak(), vec2(120.0, 10.0));
+    ui.colored_label(
+        if modul.is_input_gate_open() {
+            Color32::from_rgba_unmultiplied(255, 255, 255, 20)
+        } else {
+            Color32::RED
+        },
+        "gate",
+    );
+}
+
+/// Left and right side of the master bus, and how far it is ducked if it has a ducker
+pub fn draw_master_meter(ui: &mut Ui, modul: &Modul) {
+    ui.label("out");
+    ui.vertical(|ui| {
+        ui.spacing_mut().item_spacing.y = 1.0;
+        for peak in modul.get_master_peaks() {
+            draw_meter(ui, peak, vec2(60.0, 4.0));
+        }
+        if modul.get_ducker(TAPE_COUNT).key.is_some() {
+            draw_gain_reduction(ui, modul.get_gain_reduction(TAPE_COUNT), vec2(60.0, 4.0));
+        }
+    });
+}
+
+/// Grows from the right with the reduction in dB, like the needle of a compressor
+pub fn draw_gain_reduction(ui: &mut Ui, reduction: f32, size: Vec2) {
+    let level = (reduction / GAIN_REDUCTION_RANGE).clamp(0.0, 1.0);
+    let (_id, rect) = ui.allocate_space(size);
+    let mut filled = rect;
+    filled.set_left(rect.right() - rect.width() * level);
+    ui.painter().rect_filled(rect, 0.0, Color32::from_gray(40));
+    ui.painter()
+        .rect_filled(filled, 0.0, Color32::from_rgb(255, 140, 0));
+}
+
+fn draw_meter(ui: &mut Ui, peak: f32, size: Vec2) {
+    let peak = gain_to_db(peak);
+    let level = ((peak - METER_FLOOR) / -METER_FLOOR).clamp(0.0, 1.0);
+
+    let (_id, rect) = ui.allocate_space(size);
+    let color = if peak > -1.0 {
+        Color32::RED
+    } else if peak > -12.0 {
+        Color32::YELLOW
+    } else {
+        Color32::GREEN
+    };
+    let mut filled = rect;
+    filled.set_width(rect.width() * level);
+    ui.painter().rect_filled(rect, 0.0, Color32::from_gray(40));
+    ui.painter().rect_filled(filled, 0.0, color);
+}
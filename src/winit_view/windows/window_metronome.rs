@@ -24,6 +24,7 @@ impl WindowMetronome {
     }
 }
 impl Drawable for WindowMetronome {
+    #[allow(clippy::manual_is_multiple_of)]
     fn draw(&mut self, ctx: &egui::Context, modul: &mut Modul) {
         let Self {
             is_running,
@@ -32,7 +33,7 @@ impl Drawable for WindowMetronome {
 
         egui::Window::new("metronome").show(ctx, |ui| {
             ctx.request_repaint();
-            ui.label(format!("time: {}", modul.get_audio_index()));
+            ui.label(format!("position: {}", modul.get_musical_position()));
             ui.label("sign: 4/4");
             ui.checkbox(is_running, "beep").changed();
             {
@@ -44,8 +45,8 @@ impl Drawable for WindowMetronome {
             let to_screen =
                 emath::RectTransform::from_to(Rect::from_x_y_ranges(0.0..=1.0, -1.0..=1.0), rect);
             let mut shapes = vec![];
-            let s = texture.size_vec2();
-            let id = texture.id();
+            let _s = texture.size_vec2();
+            let _id = texture.id();
             // ui.horizontal(|ui| {
             //     ui.image(id, s);
             //     ui.image(id, s);
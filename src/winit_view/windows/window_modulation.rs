@@ -0,0 +1,118 @@
+use super::Drawable;
+use crate::core::{Modul, TAPE_COUNT};
+use crate::features::{
+    ModulationRoute, ModulationSettings, ModulationSource, LFO_COUNT, LFO_SHAPES,
+    MAX_MODULATION_ROUTES, MODULATION_TARGETS,
+};
+use egui::*;
+
+/// LFOs and envelope followers routed to the parameters of the tapes
+#[derive(Default)]
+pub struct WindowModulation {
+    settings: ModulationSettings,
+}
+
+impl Drawable for WindowModulation {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        let settings = &mut self.settings;
+        egui::Window::new("modulation").show(egui_ctx, |ui| {
+            let mut changed = false;
+            ui.label("LFOs");
+            Grid::new("lfos").show(ui, |ui| {
+                for (index, lfo) in settings.lfos.iter_mut().enumerate() {
+                    ui.label(format!("LFO {}", index + 1));
+                    ComboBox::from_id_source(("lfo", index))
+                        .width(80.0)
+                        .selected_text(lfo.shape.to_string())
+                        .show_ui(ui, |ui| {
+                            for shape in LFO_SHAPES {
+                                changed |= ui
+                                    .selectable_value(&mut lfo.shape, shape, shape.to_string())
+                                    .changed();
+                            }
+                        });
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut lfo.beats)
+                                .clamp_range(0.25..=64.0)
+                                .speed(0.25)
+                                .suffix(" beats"),
+                        )
+                        .changed();
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            ui.label("routes");
+            let mut removed = None;
+            Grid::new("routes").striped(true).show(ui, |ui| {
+                for (index, route) in settings.routes_mut().iter_mut().enumerate() {
+                    changed |= draw_route(ui, index, route);
+                    if ui.small_button("x").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = removed {
+                settings.remove(index);
+                changed = true;
+            }
+            ui.add_enabled_ui(settings.routes().len() < MAX_MODULATION_ROUTES, |ui| {
+                if ui.button("add route").clicked() {
+                    changed |= settings.push(ModulationRoute::default());
+                }
+            });
+
+            if changed {
+                modul.set_modulation(*settings);
+            }
+        });
+    }
+}
+
+/// Source, tape, parameter and depth, returns true if the route changed
+fn draw_route(ui: &mut Ui, index: usize, route: &mut ModulationRoute) -> bool {
+    let mut changed = false;
+    let sources = (0..LFO_COUNT)
+        .map(ModulationSource::Lfo)
+        .chain((0..TAPE_COUNT).map(ModulationSource::Tape))
+        .chain(std::iter::once(ModulationSource::Input));
+    ComboBox::from_id_source(("source", index))
+        .width(90.0)
+        .selected_text(route.source.to_string())
+        .show_ui(ui, |ui| {
+            for source in sources {
+                changed |= ui
+                    .selectable_value(&mut route.source, source, source.to_string())
+                    .changed();
+            }
+        });
+    ui.label("→");
+    ComboBox::from_id_source(("tape", index))
+        .width(60.0)
+        .selected_text(format!("tape {}", route.tape + 1))
+        .show_ui(ui, |ui| {
+            for id in 0..TAPE_COUNT {
+                changed |= ui
+                    .selectable_value(&mut route.tape, id, format!("tape {}", id + 1))
+                    .changed();
+            }
+        });
+    ComboBox::from_id_source(("target", index))
+        .width(70.0)
+        .selected_text(route.target.to_string())
+        .show_ui(ui, |ui| {
+            for target in MODULATION_TARGETS {
+                changed |= ui
+                    .selectable_value(&mut route.target, target, target.to_string())
+                    .changed();
+            }
+        });
+    changed |= ui
+        .add(Slider::new(&mut route.depth, -1.0..=1.0).text("depth"))
+        .changed();
+
+    changed
+}
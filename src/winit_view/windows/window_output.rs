@@ -0,0 +1,70 @@
+use super::Drawable;
+use crate::core::{Modul, OutputSettings};
+use egui::*;
+
+#[derive(Default)]
+pub struct WindowOutput {
+    settings: OutputSettings,
+}
+
+impl Drawable for WindowOutput {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        let settings = &mut self.settings;
+        let pair_count = (modul.stats.output_channel_count as usize).div_ceil(2);
+        egui::Window::new("output").show(egui_ctx, |ui| {
+            let mut changed = false;
+            ComboBox::from_label("main mix")
+                .selected_text(pair_label(settings.main_pair))
+                .show_ui(ui, |ui| {
+                    for pair in 0..pair_count {
+                        changed |= ui
+                            .selectable_value(&mut settings.main_pair, pair, pair_label(pair))
+                            .changed();
+                    }
+                });
+            // The cue mix can't share the pair of the main mix
+            if settings.cue_pair == Some(settings.main_pair) {
+                settings.cue_pair = None;
+                changed = true;
+            }
+            ComboBox::from_label("cue mix")
+                .selected_text(settings.cue_pair.map_or("off".to_string(), pair_label))
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut settings.cue_pair, None, "off")
+                        .changed();
+                    for pair in (0..pair_count).filter(|pair| *pair != settings.main_pair) {
+                        changed |= ui
+                            .selectable_value(&mut settings.cue_pair, Some(pair), pair_label(pair))
+                            .changed();
+                    }
+                });
+            ui.separator();
+            ui.add_enabled_ui(settings.cue_pair.is_some(), |ui| {
+                changed |= ui
+                    .add(Slider::new(&mut settings.cue_tape_level, 0.0..=1.0).text("tapes"))
+                    .changed();
+                changed |= ui
+                    .add(
+                        Slider::new(&mut settings.cue_metronome_level, 0.0..=1.0).text("metronome"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(Slider::new(&mut settings.cue_input_level, 0.0..=1.0).text("input"))
+                    .changed();
+            });
+            if settings.cue_pair.is_none() {
+                ui.label("without a cue mix the metronome is in the main mix");
+            }
+
+            if changed {
+                modul.set_output_settings(*settings);
+            }
+        });
+    }
+}
+
+/// Output channels are counted from 1 like on the device
+fn pair_label(pair: usize) -> String {
+    format!("out {}/{}", pair * 2 + 1, pair * 2 + 2)
+}
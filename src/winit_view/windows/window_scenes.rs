@@ -0,0 +1,55 @@
+use super::Drawable;
+use crate::core::{Modul, SCENE_COUNT};
+use egui::*;
+
+/// Scenes keep the volume, pan, mute and solo of every tape
+pub struct WindowScenes {
+    fade_time: f32, // seconds
+    is_quantized: bool,
+}
+
+impl Default for WindowScenes {
+    fn default() -> Self {
+        Self {
+            fade_time: 0.0,
+            is_quantized: true,
+        }
+    }
+}
+
+impl WindowScenes {
+    pub fn recall(&self, modul: &mut Modul, index: usize) {
+        modul.recall_scene(index, self.fade_time, self.is_quantized);
+    }
+}
+
+impl Drawable for WindowScenes {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        egui::Window::new("scenes").show(egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                for index in 0..SCENE_COUNT {
+                    let text = RichText::new((index + 1).to_string());
+                    let text = if modul.is_scene_stored(index) {
+                        text.strong()
+                    } else {
+                        text.weak()
+                    };
+                    if ui.button(text).clicked() {
+                        self.recall(modul, index);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("store");
+                for index in 0..SCENE_COUNT {
+                    if ui.small_button((index + 1).to_string()).clicked() {
+                        modul.store_scene(index);
+                    }
+                }
+            });
+            ui.separator();
+            ui.add(Slider::new(&mut self.fade_time, 0.0..=10.0).text("crossfade (sec)"));
+            ui.checkbox(&mut self.is_quantized, "recall on the next bar");
+        });
+    }
+}
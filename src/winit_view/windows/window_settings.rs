@@ -1,12 +1,137 @@
 use super::{Drawable, Modul};
+use crate::core::{Quantize, RecordSettings, MAX_PRE_ROLL, TAPE_COUNT};
+use egui::{DragValue, Slider};
 
-#[derive(Default)]
-pub struct WindowSettings {}
+pub struct WindowSettings {
+    record_settings: RecordSettings,
+    capture_bar_count: usize,
+    quantize: Quantize,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            record_settings: RecordSettings::default(),
+            capture_bar_count: 1,
+            quantize: Quantize::Off,
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn get_capture_bar_count(&self) -> usize {
+        self.capture_bar_count
+    }
+}
 
 impl Drawable for WindowSettings {
     fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        let Self {
+            record_settings,
+            capture_bar_count,
+            quantize,
+        } = self;
         egui::Window::new("Settings").show(egui_ctx, |ui| {
             ui.label("Settings");
+            ui.separator();
+            ui.label("recording");
+            let mut changed = false;
+            changed |= ui
+                .add(
+                    Slider::new(&mut record_settings.threshold, -60.0..=0.0).text("threshold (dB)"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut record_settings.pre_roll, 0.0..=MAX_PRE_ROLL)
+                        .text("pre-roll (sec)"),
+                )
+                .changed();
+            changed |= ui
+                .checkbox(&mut record_settings.is_one_loop, "stop after one loop")
+                .changed();
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .checkbox(&mut record_settings.is_auto_advance, "auto-advance after")
+                    .changed();
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut record_settings.auto_advance_loop_count)
+                            .clamp_range(0..=16),
+                    )
+                    .changed();
+                ui.label("loops, 0 waits until the recording is stopped");
+            });
+            if changed {
+                modul.set_record_settings(*record_settings);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("capture");
+                for bar_count in [1, 2, 4] {
+                    ui.radio_value(capture_bar_count, bar_count, format!("{} bars", bar_count));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("quantize record, mute and solo");
+                let mut changed = false;
+                changed |= ui.radio_value(quantize, Quantize::Off, "off").changed();
+                changed |= ui.radio_value(quantize, Quantize::Beat, "beat").changed();
+                changed |= ui.radio_value(quantize, Quantize::Bar, "bar").changed();
+                if changed {
+                    modul.set_quantize(*quantize);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("tape length");
+                for factor in [2, 3, 4] {
+                    if ui.button(format!("×{}", factor)).clicked() {
+                        modul.multiply_tape(factor);
+                    }
+                }
+                for divisor in [2, 4] {
+                    if ui.button(format!("÷{}", divisor)).clicked() {
+                        modul.divide_tape(divisor);
+                    }
+                }
+            });
+            ui.separator();
+            ui.collapsing("punch in/out, in beats from the start of the tape", |ui| {
+                egui::Grid::new("punch").show(ui, |ui| {
+                    for id in 0..TAPE_COUNT {
+                        draw_punch_region(ui, modul, id);
+                        ui.end_row();
+                    }
+                });
+            });
         });
     }
 }
+
+fn draw_punch_region(ui: &mut egui::Ui, modul: &mut Modul, id: usize) {
+    let region = modul.get_punch_region(id);
+    let mut is_on = region.is_some();
+    let mut region = region.unwrap_or_default();
+    let mut changed = ui
+        .checkbox(&mut is_on, format!("tape {}", id + 1))
+        .changed();
+    ui.add_enabled_ui(is_on, |ui| {
+        changed |= ui
+            .add(
+                DragValue::new(&mut region.start)
+                    .speed(0.25)
+                    .clamp_range(0.0..=region.end),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                DragValue::new(&mut region.end)
+                    .speed(0.25)
+                    .clamp_range(region.start..=f64::MAX),
+            )
+            .changed();
+    });
+    if changed {
+        modul.set_punch_region(id, is_on.then_some(region));
+    }
+}
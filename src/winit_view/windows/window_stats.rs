@@ -14,8 +14,25 @@ impl Drawable for WindowStats {
             egui_ctx.request_repaint();
             ui.label(RichText::new(format!("FPS: {0:0.2}", modul.stats.fps)).color(Color32::RED));
             ui.label(format!("time: {:0.1} sec", instant.elapsed().as_secs_f32()));
-            ui.label(format!("audio index: {:0.1}", modul.get_audio_index()));
-            ui.label(format!("bpm: {}", modul.stats.bpm));
+            ui.label(format!(
+                "position: {} ({:0.2} sec)",
+                modul.get_musical_position(),
+                modul.get_seconds()
+            ));
+            let latency = modul.get_latency();
+            ui.label(format!(
+                "buffered input: {:0.1} ms ({} frames)",
+                latency.milliseconds, latency.frames
+            ));
+            ui.label(format!(
+                "overruns: {}, underruns: {}",
+                latency.overruns, latency.underruns
+            ));
+            ui.label(format!(
+                "dropped input frames: {}",
+                modul.get_dropped_input_frames()
+            ));
+            ui.label(format!("bpm: {:0.2}", modul.stats.bpm));
             ui.label(format!("bar count: {}", modul.stats.bar_count));
             ui.label(format!("bar length: {} sec", modul.stats.bar_length));
             ui.colored_label(
@@ -26,6 +43,10 @@ impl Drawable for WindowStats {
                 "input channel count: {}",
                 modul.stats.input_channel_count
             ));
+            ui.label(format!(
+                "input sample format: {}",
+                modul.stats.input_sample_format
+            ));
             ui.label(format!(
                 "input buffer size: {}",
                 modul.stats.input_buffer_size
@@ -38,6 +59,10 @@ impl Drawable for WindowStats {
                 "output channel count: {}",
                 modul.stats.output_channel_count
             ));
+            ui.label(format!(
+                "output sample format: {}",
+                modul.stats.output_sample_format
+            ));
             ui.label(format!(
                 "output buffer size: {}",
                 modul.stats.output_buffer_size
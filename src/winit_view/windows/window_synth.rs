@@ -0,0 +1,240 @@
+use super::Drawable;
+use crate::core::{Modul, TAPE_COUNT};
+use crate::features::WAVEFORMS;
+use egui::*;
+
+/// Without an octave shift the first key of the keyboard is middle C
+const FIRST_NOTE: u8 = 60;
+const MAX_OCTAVE_SHIFT: i8 = 3;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+/// In key mode the middle row plays the white keys and the row above the black keys
+const NOTE_KEYS: [Key; 13] = [
+    Key::A,
+    Key::W,
+    Key::S,
+    Key::E,
+    Key::D,
+    Key::F,
+    Key::T,
+    Key::G,
+    Key::Y,
+    Key::H,
+    Key::U,
+    Key::J,
+    Key::K,
+];
+const OCTAVE_DOWN_KEY: Key = Key::Z;
+const OCTAVE_UP_KEY: Key = Key::X;
+const VELOCITY_DOWN_KEY: Key = Key::C;
+const VELOCITY_UP_KEY: Key = Key::V;
+const VELOCITIES: [f32; 4] = [0.25, 0.5, 0.75, 1.0];
+
+/// Settings of the built-in synth and one octave of keys to play it with
+/// Tapes that are routed to the synth in 'Input' record it
+pub struct WindowSynth {
+    /// The key that is held down with the pointer
+    note: Option<u8>,
+    octave_shift: i8,
+    /// One of VELOCITIES
+    velocity: usize,
+    /// The note every note key started, the octave can change while it is held
+    held_notes: [Option<u8>; NOTE_KEYS.len()],
+}
+
+impl Default for WindowSynth {
+    fn default() -> Self {
+        Self {
+            note: None,
+            octave_shift: 0,
+            velocity: VELOCITIES.len() - 1,
+            held_notes: [None; NOTE_KEYS.len()],
+        }
+    }
+}
+
+impl Drawable for WindowSynth {
+    fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut Modul) {
+        egui::Window::new("synth").show(egui_ctx, |ui| {
+            let mut settings = modul.get_synth();
+            let mut changed = false;
+            ComboBox::from_label("sample")
+                .selected_text(
+                    settings
+                        .sample
+                        .map_or("off".to_string(), |id| format!("tape {}", id + 1)),
+                )
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut settings.sample, None, "off")
+                        .changed();
+                    for id in 0..TAPE_COUNT {
+                        changed |= ui
+                            .selectable_value(
+                                &mut settings.sample,
+                                Some(id),
+                                format!("tape {}", id + 1),
+                            )
+                            .changed();
+                    }
+                });
+            ComboBox::from_label("waveform")
+                .selected_text(settings.waveform.to_string())
+                .show_ui(ui, |ui| {
+                    for waveform in WAVEFORMS {
+                        changed |= ui
+                            .selectable_value(
+                                &mut settings.waveform,
+                                waveform,
+                                waveform.to_string(),
+                            )
+                            .changed();
+                    }
+                });
+            ui.separator();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.attack, 1.0..=2000.0)
+                        .logarithmic(true)
+                        .text("attack (ms)"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.decay, 1.0..=2000.0)
+                        .logarithmic(true)
+                        .text("decay (ms)"),
+                )
+                .changed();
+            changed |= ui
+                .add(Slider::new(&mut settings.sustain, 0.0..=1.0).text("sustain"))
+                .changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.release, 1.0..=5000.0)
+                        .logarithmic(true)
+                        .text("release (ms)"),
+                )
+                .changed();
+            ui.separator();
+            changed |= ui
+                .add(
+                    Slider::new(&mut settings.cutoff, 20.0..=20000.0)
+                        .logarithmic(true)
+                        .text("cutoff (Hz)"),
+                )
+                .changed();
+            changed |= ui
+                .add(Slider::new(&mut settings.volume, -48.0..=0.0).text("volume (dB)"))
+                .changed();
+            if changed {
+                modul.set_synth(settings);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(format!("octave {:+}", self.octave_shift));
+                ui.label(format!("velocity {}", VELOCITIES[self.velocity]));
+            });
+            self.draw_keyboard(ui, modul);
+        });
+    }
+}
+
+impl WindowSynth {
+    fn first_note(&self) -> u8 {
+        (FIRST_NOTE as i8 + self.octave_shift * 12) as u8
+    }
+
+    /// A note plays while its key is held down
+    fn draw_keyboard(&mut self, ui: &mut Ui, modul: &mut Modul) {
+        let mut note = None;
+        let first_note = self.first_note();
+        ui.horizontal(|ui| {
+            for key in first_note..=first_note + 12 {
+                let name = NOTE_NAMES[(key % 12) as usize];
+                if ui.button(name).is_pointer_button_down_on() {
+                    note = Some(key);
+                }
+            }
+        });
+        if note != self.note {
+            if let Some(note) = self.note {
+                modul.note_off(note);
+            }
+            if let Some(note) = note {
+                modul.note_on(note, VELOCITIES[self.velocity]);
+            }
+            self.note = note;
+        }
+    }
+
+    /// Key mode, the note keys play the synth and the keys around them shift the octave and
+    /// the velocity. Their events are taken, so the shortcuts on the same keys don't fire
+    pub fn play_keys(&mut self, ui: &Ui, modul: &mut Modul) {
+        let is_instrument_key = |key: &Key| {
+            NOTE_KEYS.contains(key)
+                || [
+                    OCTAVE_DOWN_KEY,
+                    OCTAVE_UP_KEY,
+                    VELOCITY_DOWN_KEY,
+                    VELOCITY_UP_KEY,
+                ]
+                .contains(key)
+        };
+        let mut events = Vec::new();
+        ui.input_mut(|i| {
+            i.events.retain(|event| match event {
+                Event::Key {
+                    key,
+                    pressed,
+                    repeat,
+                    ..
+                } if is_instrument_key(key) => {
+                    // Held keys repeat, a note only starts on the first press
+                    if !repeat {
+                        events.push((*key, *pressed));
+                    }
+                    false
+                }
+                _ => true,
+            });
+        });
+
+        for (key, pressed) in events {
+            match key {
+                OCTAVE_DOWN_KEY if pressed => {
+                    self.octave_shift = (self.octave_shift - 1).max(-MAX_OCTAVE_SHIFT);
+                }
+                OCTAVE_UP_KEY if pressed => {
+                    self.octave_shift = (self.octave_shift + 1).min(MAX_OCTAVE_SHIFT);
+                }
+                VELOCITY_DOWN_KEY if pressed => self.velocity = self.velocity.saturating_sub(1),
+                VELOCITY_UP_KEY if pressed => {
+                    self.velocity = (self.velocity + 1).min(VELOCITIES.len() - 1);
+                }
+                _ => {
+                    let Some(index) = NOTE_KEYS.iter().position(|note_key| *note_key == key) else {
+                        continue;
+                    };
+                    if let Some(note) = self.held_notes[index].take() {
+                        modul.note_off(note);
+                    }
+                    if pressed {
+                        let note = self.first_note() + index as u8;
+                        modul.note_on(note, VELOCITIES[self.velocity]);
+                        self.held_notes[index] = Some(note);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Notes that are held when key mode is switched off would never be released
+    pub fn release_keys(&mut self, modul: &mut Modul) {
+        for note in self.held_notes.iter_mut().filter_map(Option::take) {
+            modul.note_off(note);
+        }
+    }
+}
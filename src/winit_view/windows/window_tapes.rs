@@ -1,5 +1,6 @@
 use crate::core::Modul;
 use crate::core::{SAMPLE_GRAPH_SIZE, TAPE_COUNT};
+use crate::features::AUTOMATION_MODES;
 use egui::*;
 
 use super::Drawable;
@@ -8,8 +9,11 @@ pub struct WindowTapes {
     primary_tape: usize,
     secondary_tapes: [bool; TAPE_COUNT],
     tape_volumes: [f32; TAPE_COUNT],
+    tape_pans: [f32; TAPE_COUNT],
     tape_mute_states: [bool; TAPE_COUNT],
     tape_solo_states: [bool; TAPE_COUNT],
+    /// The take of every bar of the primary tape
+    comp: Vec<Option<usize>>,
 }
 
 impl Default for WindowTapes {
@@ -18,8 +22,10 @@ impl Default for WindowTapes {
             primary_tape: 0,
             secondary_tapes: [false; TAPE_COUNT],
             tape_volumes: [1.0; TAPE_COUNT],
+            tape_pans: [0.0; TAPE_COUNT],
             tape_mute_states: [false; TAPE_COUNT],
             tape_solo_states: [false; TAPE_COUNT],
+            comp: vec![],
         }
     }
 }
@@ -30,12 +36,24 @@ impl Drawable for WindowTapes {
             primary_tape,
             secondary_tapes,
             tape_volumes,
+            tape_pans,
             tape_mute_states,
             tape_solo_states,
+            comp,
         } = self;
 
         egui::Window::new("tapes").show(egui_ctx, |ui| {
             egui_ctx.request_repaint();
+            // Auto-advance moves on to the next tape by itself
+            *primary_tape = modul.get_primary_tape();
+            // Scenes change the mix of every tape at once
+            for id in 0..TAPE_COUNT {
+                let mix = modul.get_tape_mix(id);
+                tape_volumes[id] = mix.volume;
+                tape_pans[id] = mix.pan;
+                tape_mute_states[id] = mix.is_muted;
+                tape_solo_states[id] = mix.is_solo;
+            }
 
             ui.colored_label(
                 if modul.is_recording_playback() {
@@ -45,19 +63,22 @@ impl Drawable for WindowTapes {
                 },
                 "MAIN TAPE ⏺",
             );
-            for i in 0..TAPE_COUNT {
+            for (i, is_secondary) in secondary_tapes.iter().enumerate() {
                 draw_tape(
                     ui,
                     primary_tape,
-                    secondary_tapes[i],
+                    *is_secondary,
                     tape_volumes,
+                    tape_pans,
                     tape_mute_states,
                     tape_solo_states,
                     modul,
                     i,
                 );
             }
+            draw_comp(ui, modul, *primary_tape, comp);
 
+            #[allow(deprecated)]
             let is_shift_pressed = ui.input(|i| i.modifiers).matches(Modifiers::SHIFT);
             if ui.input(|i| i.key_pressed(Key::Num1)) {
                 select_tape(modul, primary_tape, 0, secondary_tapes, is_shift_pressed);
@@ -92,6 +113,16 @@ impl Drawable for WindowTapes {
                     }
                 }
             }
+            if ui.input(|i| i.key_pressed(Key::X)) {
+                if is_shift_pressed {
+                    modul.divide_tape(2);
+                } else {
+                    modul.multiply_tape(2);
+                }
+            }
+            if ui.input(|i| i.key_pressed(Key::E)) {
+                modul.toggle_record_enabled(*primary_tape);
+            }
             if ui.input(|i| i.key_pressed(Key::N)) {
                 modul.merge_tapes();
             }
@@ -126,6 +157,23 @@ impl Drawable for WindowTapes {
                 }
                 modul.volume_down();
             }
+            // Same steps and limits as Tape::pan_left and Tape::pan_right
+            let pan_step = if ui.input(|i| i.key_pressed(Key::ArrowLeft)) {
+                modul.pan_left();
+                -0.1
+            } else if ui.input(|i| i.key_pressed(Key::ArrowRight)) {
+                modul.pan_right();
+                0.1
+            } else {
+                0.0
+            };
+            if pan_step != 0.0 {
+                for i in 0..TAPE_COUNT {
+                    if i == *primary_tape || secondary_tapes[i] {
+                        tape_pans[i] = (tape_pans[i] + pan_step).clamp(-1.0, 1.0);
+                    }
+                }
+            }
         });
     }
 }
@@ -148,11 +196,93 @@ fn select_tape(
     }
 }
 
+/// Picks a take for every bar of the tape, flattening writes them to the tape as a new take
+fn draw_comp(ui: &mut Ui, modul: &mut Modul, id: usize, comp: &mut Vec<Option<usize>>) {
+    comp.resize(modul.get_bar_count(id), None);
+    let takes = modul.get_takes(id).to_vec();
+    ui.collapsing(format!("comp tape {}", id + 1), |ui| {
+        ui.horizontal_wrapped(|ui| {
+            for (bar, take) in comp.iter_mut().enumerate() {
+                ComboBox::from_id_source(("comp", bar))
+                    .width(60.0)
+                    .selected_text(match take {
+                        Some(number) => format!("{}: take {}", bar + 1, number),
+                        None => format!("{}: -", bar + 1),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(take, None, "-");
+                        for number in &takes {
+                            ui.selectable_value(take, Some(*number), format!("take {}", number));
+                        }
+                    });
+            }
+        });
+        if ui.button("flatten").clicked() {
+            modul.flatten_comp(id, std::mem::take(comp));
+        }
+    });
+}
+
+/// The take that is playing, takes are kept for every recording pass
+fn draw_take_selection(ui: &mut Ui, modul: &mut Modul, id: usize) {
+    if modul.get_takes(id).is_empty() {
+        return;
+    }
+    let active = modul.get_active_take(id);
+    let mut selected = active;
+    ComboBox::from_id_source(("take", id))
+        .width(60.0)
+        .selected_text(active.map_or("take -".to_string(), |number| format!("take {}", number)))
+        .show_ui(ui, |ui| {
+            for number in modul.get_takes(id) {
+                ui.selectable_value(&mut selected, Some(*number), format!("take {}", number));
+            }
+        });
+    if let Some(number) = selected.filter(|_| selected != active) {
+        modul.select_take(id, number);
+    }
+}
+
+/// Touch and latch write the lane while the volume or mute of the tape is changed
+fn draw_automation_mode(ui: &mut Ui, modul: &mut Modul, id: usize) {
+    let mode = modul.get_automation_mode(id);
+    let mut selected = mode;
+    ComboBox::from_id_source(("automation", id))
+        .width(70.0)
+        .selected_text(format!("auto {}", mode))
+        .show_ui(ui, |ui| {
+            for mode in AUTOMATION_MODES {
+                ui.selectable_value(&mut selected, mode, mode.to_string());
+            }
+            ui.separator();
+            if ui.button("clear lane").clicked() {
+                modul.clear_automation(id);
+            }
+        });
+    if selected != mode {
+        modul.set_automation_mode(id, selected);
+    }
+}
+
+/// C in the center, L or R with the amount of pan otherwise
+fn pan_label(pan: f32) -> String {
+    let amount = (pan.abs() * 100.0).round();
+    if amount == 0.0 {
+        "C".to_string()
+    } else if pan < 0.0 {
+        format!("L{}", amount)
+    } else {
+        format!("R{}", amount)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_tape(
     ui: &mut Ui,
     primary_tape: &mut usize,
     is_secondary: bool,
     tape_volumes: &mut [f32; TAPE_COUNT],
+    tape_pans: &mut [f32; TAPE_COUNT],
     tape_mute_states: &mut [bool; TAPE_COUNT],
     tape_solo_states: &mut [bool; TAPE_COUNT],
     modul: &mut Modul,
@@ -172,9 +302,15 @@ fn draw_tape(
             let grayed_out = Color32::from_rgba_unmultiplied(255, 255, 255, 20);
             ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
                 // ui.group(|ui| {
+                // Record enabled tapes are recorded together with the primary tape
+                let is_recorded = *primary_tape == id || modul.is_record_enabled(id);
                 ui.colored_label(
-                    if *primary_tape == id && modul.is_recording() {
+                    if is_recorded && modul.is_recording() {
                         Color32::RED
+                    } else if is_recorded && modul.is_armed() {
+                        Color32::YELLOW
+                    } else if modul.is_record_enabled(id) {
+                        Color32::from_rgb(120, 40, 40)
                     } else {
                         grayed_out
                     },
@@ -205,7 +341,26 @@ fn draw_tape(
                     "🇸",
                 );
 
+                if modul.get_ducker(id).key.is_some() {
+                    super::window_input::draw_gain_reduction(
+                        ui,
+                        modul.get_gain_reduction(id),
+                        vec2(30.0, 6.0),
+                    );
+                }
                 ui.label(format!("{:0.2}", tape_volumes[id]));
+                ui.label(pan_label(tape_pans[id]));
+                ui.label(modul.get_input_route(id).to_string());
+                draw_take_selection(ui, modul, id);
+                draw_automation_mode(ui, modul, id);
+                let ratio = modul.get_tape_ratio(id);
+                if ratio != 1.0 {
+                    ui.label(if ratio > 1.0 {
+                        format!("×{}", ratio)
+                    } else {
+                        format!("÷{}", 1.0 / ratio)
+                    });
+                }
                 // });
             });
         });
@@ -259,7 +414,37 @@ fn draw_tape(
         // ));
         // Second waveform
 
-        let time = modul.get_audio_index() as f32 / modul.tape_length as f32;
+        if let Some((start, end)) = modul.get_punch_ratio(id) {
+            let region = Rect::from_min_max(
+                to_screen * pos2(start, -1.0),
+                to_screen * pos2(end.min(1.0), 1.0),
+            );
+            ui.painter()
+                .rect_filled(region, 0.0, Color32::from_rgba_unmultiplied(255, 0, 0, 30));
+        }
+
+        // The lane is one loop long, a multiplied tape plays it once per loop
+        if let Some(graph) = modul.get_automation_graph(id) {
+            let ratio = modul.get_tape_ratio(id);
+            for pass in 0..(ratio.ceil() as usize) {
+                let points: Vec<Pos2> = graph
+                    .iter()
+                    .enumerate()
+                    .map(|(i, volume)| {
+                        let x = (pass as f32 + i as f32 / SAMPLE_GRAPH_SIZE as f32) / ratio;
+                        (x, volume)
+                    })
+                    .filter(|(x, _)| *x <= 1.0)
+                    .map(|(x, volume)| to_screen * pos2(x, 1.0 - 2.0 * volume))
+                    .collect();
+                shapes.push(epaint::Shape::line(
+                    points,
+                    Stroke::new(1.0, Color32::YELLOW),
+                ));
+            }
+        }
+
+        let time = modul.get_tape_position(id);
         let points: Vec<Pos2> = (0..2)
             .map(|i| to_screen * pos2(time, -1.0 + 2.0 * i as f32))
             .collect();
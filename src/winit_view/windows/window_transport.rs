@@ -0,0 +1,34 @@
+use crate::core::{Modul, TransportState};
+use egui::*;
+
+/// Transport buttons and the musical position for the top panel
+/// `locate_bar` is counted from 1 here, like the position display
+pub fn draw_transport(ui: &mut Ui, modul: &mut Modul, locate_bar: &mut usize) {
+    let transport = modul.get_transport();
+    if ui
+        .selectable_label(transport == TransportState::Playing, "▶")
+        .clicked()
+    {
+        modul.play();
+    }
+    if ui
+        .selectable_label(transport == TransportState::Paused, "⏸")
+        .clicked()
+    {
+        modul.pause();
+    }
+    if ui
+        .selectable_label(transport == TransportState::Stopped, "⏹")
+        .clicked()
+    {
+        modul.stop();
+    }
+    if ui.button("⏮").clicked() {
+        modul.locate(0);
+    }
+    ui.label(RichText::new(modul.get_musical_position().to_string()).monospace());
+    ui.add(DragValue::new(locate_bar).clamp_range(1..=999));
+    if ui.button("locate").clicked() {
+        modul.locate(*locate_bar - 1);
+    }
+}
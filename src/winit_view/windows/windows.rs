@@ -1,6 +1,23 @@
+use crate::core::TransportState;
 use egui::{Key, Modifiers};
+
 // use std::collections::HashMap;
 
+/// Switches key mode, the letter keys of the keyboard instrument play the synth
+const KEY_MODE_KEY: Key = Key::Backtick;
+
+/// Recall scenes 1 to 8, shift stores them
+const SCENE_KEYS: [Key; 8] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+];
+
 pub trait Drawable {
     fn draw(&mut self, egui_ctx: &egui::Context, modul: &mut super::Modul);
 }
@@ -19,6 +36,22 @@ pub struct Windows {
     window_log: super::window_log::WindowLog,
     show_settings: bool,
     window_settings: super::window_settings::WindowSettings,
+    show_input: bool,
+    window_input: super::window_input::WindowInput,
+    show_output: bool,
+    window_output: super::window_output::WindowOutput,
+    show_scenes: bool,
+    window_scenes: super::window_scenes::WindowScenes,
+    show_arrangement: bool,
+    window_arrangement: super::window_arrangement::WindowArrangement,
+    show_modulation: bool,
+    window_modulation: super::window_modulation::WindowModulation,
+    show_ducking: bool,
+    window_ducking: super::window_ducking::WindowDucking,
+    show_synth: bool,
+    window_synth: super::window_synth::WindowSynth,
+    is_key_mode: bool,
+    locate_bar: usize,
     // inventory: HashMap<bool, dyn Drawable>,
 }
 
@@ -38,6 +71,22 @@ impl Windows {
             window_log: super::window_log::WindowLog::default(),
             show_settings: false,
             window_settings: super::window_settings::WindowSettings::default(),
+            show_input: false,
+            window_input: super::window_input::WindowInput::default(),
+            show_output: false,
+            window_output: super::window_output::WindowOutput::default(),
+            show_scenes: false,
+            window_scenes: super::window_scenes::WindowScenes::default(),
+            show_arrangement: false,
+            window_arrangement: super::window_arrangement::WindowArrangement::default(),
+            show_modulation: false,
+            window_modulation: super::window_modulation::WindowModulation::default(),
+            show_ducking: false,
+            window_ducking: super::window_ducking::WindowDucking::default(),
+            show_synth: false,
+            window_synth: super::window_synth::WindowSynth::default(),
+            is_key_mode: false,
+            locate_bar: 1,
         }
     }
 
@@ -57,6 +106,13 @@ impl Windows {
                     ui.checkbox(&mut self.show_controls, "Controls");
                     ui.checkbox(&mut self.show_log, "Log");
                     ui.checkbox(&mut self.show_settings, "Settings");
+                    ui.checkbox(&mut self.show_input, "Input");
+                    ui.checkbox(&mut self.show_output, "Output");
+                    ui.checkbox(&mut self.show_scenes, "Scenes");
+                    ui.checkbox(&mut self.show_arrangement, "Arrangement");
+                    ui.checkbox(&mut self.show_modulation, "Modulation");
+                    ui.checkbox(&mut self.show_ducking, "Ducking");
+                    ui.checkbox(&mut self.show_synth, "Synth");
                 });
                 if ui
                     .checkbox(&mut self.is_play_through, "play through")
@@ -64,6 +120,15 @@ impl Windows {
                 {
                     modul.play_through();
                 }
+                if ui.checkbox(&mut self.is_key_mode, "keys").changed() && !self.is_key_mode {
+                    self.window_synth.release_keys(modul);
+                }
+                ui.separator();
+                super::window_transport::draw_transport(ui, modul, &mut self.locate_bar);
+                ui.separator();
+                super::window_input::draw_input_meter(ui, modul);
+                ui.separator();
+                super::window_input::draw_master_meter(ui, modul);
 
                 #[cfg(not(target_arch = "wasm32"))]
                 {
@@ -96,12 +161,49 @@ impl Windows {
         if self.show_settings {
             self.window_settings.draw(ctx, modul);
         }
+        if self.show_input {
+            self.window_input.draw(ctx, modul);
+        }
+        if self.show_output {
+            self.window_output.draw(ctx, modul);
+        }
+        if self.show_scenes {
+            self.window_scenes.draw(ctx, modul);
+        }
+        if self.show_arrangement {
+            self.window_arrangement.draw(ctx, modul);
+        }
+        if self.show_modulation {
+            self.window_modulation.draw(ctx, modul);
+        }
+        if self.show_ducking {
+            self.window_ducking.draw(ctx, modul);
+        }
+        if self.show_synth {
+            self.window_synth.draw(ctx, modul);
+        }
     }
 
     fn check_input(&mut self, ui: &mut egui::Ui, modul: &mut super::Modul) {
+        if ui.input(|i| i.key_pressed(KEY_MODE_KEY)) {
+            self.is_key_mode = !self.is_key_mode;
+            if !self.is_key_mode {
+                self.window_synth.release_keys(modul);
+            }
+        }
+        // Goes first, the keys it plays are gone for the shortcuts below and in the windows
+        if self.is_key_mode {
+            self.window_synth.play_keys(ui, modul);
+        }
         if ui.input(|i| i.key_pressed(Key::Space)) {
             modul.record();
         }
+        if ui.input(|i| i.key_pressed(Key::R)) {
+            modul.arm_record();
+        }
+        if ui.input(|i| i.key_pressed(Key::G)) {
+            modul.capture(self.window_settings.get_capture_bar_count());
+        }
         if ui.input(|i| i.key_pressed(Key::C)) {
             modul.clear();
         }
@@ -117,6 +219,27 @@ impl Windows {
         if ui.input(|i| i.key_pressed(Key::W)) {
             modul.write();
         }
+        if ui.input(|i| i.key_pressed(Key::P)) {
+            if ui.input(|i| i.modifiers == Modifiers::SHIFT) {
+                modul.stop();
+            } else if modul.get_transport() == TransportState::Playing {
+                modul.pause();
+            } else {
+                modul.play();
+            }
+        }
+        if ui.input(|i| i.key_pressed(Key::Home)) {
+            modul.locate(0);
+        }
+        for (index, key) in SCENE_KEYS.into_iter().enumerate() {
+            if ui.input(|i| i.key_pressed(key)) {
+                if ui.input(|i| i.modifiers == Modifiers::SHIFT) {
+                    modul.store_scene(index);
+                } else {
+                    self.window_scenes.recall(modul, index);
+                }
+            }
+        }
         if ui.input(|i| i.key_pressed(Key::Escape)) {
             std::process::exit(0);
         }